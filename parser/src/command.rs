@@ -1,7 +1,9 @@
 use crate::error::Error;
 use crate::ignore_block::IgnoreBlocks;
 use crate::token::Tokenizer;
-use regex::Regex;
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+use std::collections::HashMap;
 
 pub mod assign;
 pub mod close;
@@ -13,6 +15,7 @@ pub mod prioritize;
 pub mod relabel;
 pub mod second;
 pub mod shortcut;
+pub mod unknown;
 
 #[derive(Debug, PartialEq)]
 pub enum Command<'a> {
@@ -26,6 +29,7 @@ pub enum Command<'a> {
     Shortcut(Result<shortcut::ShortcutCommand, Error<'a>>),
     Close(Result<close::CloseCommand, Error<'a>>),
     Note(Result<note::NoteCommand, Error<'a>>),
+    Unknown(Result<unknown::UnknownCommand, Error<'a>>),
 }
 
 #[derive(Debug)]
@@ -59,8 +63,13 @@ where
 }
 
 impl<'a> Input<'a> {
+    /// Panics if `bot` is empty, as the pattern would then match everywhere.
     pub fn new(input: &'a str, bot: Vec<&'a str>) -> Input<'a> {
-        let bots: Vec<_> = bot.iter().map(|bot| format!(r"(?:@{bot}\b)")).collect();
+        assert!(!bot.is_empty(), "no bot names to look for");
+        let bots: Vec<_> = bot
+            .iter()
+            .map(|bot| format!(r"(?:@{}\b)", regex::escape(bot)))
+            .collect();
         let bot_re = Regex::new(&format!(
             r#"(?i)(?P<review>\br\?)|{bots}"#,
             bots = bots.join("|")
@@ -133,6 +142,16 @@ impl<'a> Input<'a> {
             &original_tokenizer,
         ));
 
+        // Only consulted if nothing else matched, to point out typos and
+        // unknown commands.
+        if success.is_empty() {
+            success.extend(parse_single_command(
+                unknown::UnknownCommand::parse,
+                Command::Unknown,
+                &original_tokenizer,
+            ));
+        }
+
         if success.len() > 1 {
             panic!(
                 "succeeded parsing {:?} to multiple commands: {:?}",
@@ -165,6 +184,58 @@ impl<'a> Input<'a> {
     }
 }
 
+/// Rewrites configured command aliases that directly follow a bot mention
+/// into the command they stand for, e.g. `@bot postpone` to `@bot close`.
+///
+/// `aliases` maps an alias to its replacement. Aliases are matched ignoring
+/// case and may consist of several words (`fcp merge`).
+pub fn expand_aliases<'b>(
+    input: &'b str,
+    bot: &[&str],
+    aliases: &HashMap<String, String>,
+) -> Cow<'b, str> {
+    if aliases.is_empty() {
+        return Cow::Borrowed(input);
+    }
+    let normalize = |alias: &str| {
+        alias
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    };
+    let targets: HashMap<String, &str> = aliases
+        .iter()
+        .map(|(alias, target)| (normalize(alias), target.as_str()))
+        .collect();
+    // Try longer aliases first, so that `fcp merge` wins over `fcp`.
+    let mut names: Vec<&String> = targets.keys().collect();
+    names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+    let names: Vec<_> = names
+        .iter()
+        .map(|name| {
+            name.split(' ')
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join(r"[ \t]+")
+        })
+        .collect();
+    let bots: Vec<_> = bot.iter().map(|bot| regex::escape(bot)).collect();
+    let alias_re = Regex::new(&format!(
+        r"(?i)(?P<mention>@(?:{bots})\b[ \t]+)(?P<alias>{names})\b",
+        bots = bots.join("|"),
+        names = names.join("|"),
+    ))
+    .unwrap();
+    alias_re.replace_all(input, |caps: &Captures<'_>| {
+        format!(
+            "{}{}",
+            &caps["mention"],
+            targets[&normalize(&caps["alias"])]
+        )
+    })
+}
+
 impl<'a> Iterator for Input<'a> {
     type Item = Command<'a>;
 
@@ -207,6 +278,7 @@ impl<'a> Command<'a> {
             Command::Shortcut(r) => r.is_ok(),
             Command::Close(r) => r.is_ok(),
             Command::Note(r) => r.is_ok(),
+            Command::Unknown(r) => r.is_ok(),
         }
     }
 
@@ -222,6 +294,12 @@ fn errors_outside_command_are_fine() {
     assert!(input.next().unwrap().is_ok());
 }
 
+#[test]
+#[should_panic]
+fn no_bot_names() {
+    Input::new("@bot claim", vec![]);
+}
+
 #[test]
 fn code_1() {
     let input = "`@bot modify label: +bug.`";
@@ -238,6 +316,16 @@ fn code_2() {
     assert!(input.next().is_none());
 }
 
+#[test]
+fn quoted_reply() {
+    let input = "> @bot claim
+
+I don't think we should @bot close";
+    let mut input = Input::new(input, vec!["bot"]);
+    assert_eq!(input.next(), Some(Command::Close(Ok(close::CloseCommand))));
+    assert_eq!(input.next(), None);
+}
+
 #[test]
 fn resumes_after_code() {
     // Handles a command after an ignored block.
@@ -300,6 +388,108 @@ fn multiname() {
     assert!(input.next().is_none());
 }
 
+#[test]
+fn multiple_commands() {
+    let input = "@bot claim
+@bot label +T-lang
+Some more text. @bot prioritize";
+    let mut input = Input::new(input, vec!["bot"]);
+    assert_eq!(
+        input.next(),
+        Some(Command::Assign(Ok(assign::AssignCommand::Own)))
+    );
+    assert!(matches!(input.next(), Some(Command::Relabel(Ok(_)))));
+    assert!(matches!(input.next(), Some(Command::Prioritize(Ok(_)))));
+    assert_eq!(input.next(), None);
+}
+
+#[test]
+fn unknown_command_suggestion() {
+    let input = "@bot prioritze";
+    let mut input = Input::new(input, vec!["bot"]);
+    assert!(matches!(input.next(), Some(Command::Unknown(Err(_)))));
+    assert_eq!(input.next(), None);
+}
+
+#[test]
+fn error_fragments() {
+    for (input, fragment) in [
+        ("@bot assign foo bar", "foo"),
+        ("@bot claim now", "now"),
+        ("@bot label +bug -", "-"),
+        ("@bot label: to +bug", "to"),
+        ("@bot ping .", "."),
+        ("@bot ping lang please", "please"),
+        ("@bot nominate lang now", "now"),
+        ("@bot glacier foo", "foo"),
+        ("@bot prioritze", "prioritze"),
+    ] {
+        let err = match Input::new(input, vec!["bot"]).next() {
+            Some(Command::Assign(Err(err)))
+            | Some(Command::Relabel(Err(err)))
+            | Some(Command::Ping(Err(err)))
+            | Some(Command::Nominate(Err(err)))
+            | Some(Command::Glacier(Err(err)))
+            | Some(Command::Unknown(Err(err))) => err,
+            other => panic!("expected an error for `{input}`, got {other:?}"),
+        };
+        assert_eq!(err.fragment(), fragment, "for `{input}`");
+    }
+}
+
+#[test]
+fn known_commands() {
+    // `modify` only starts a command when followed by `labels`, which the other
+    // commands either take as an argument or reject.
+    for command in unknown::KNOWN_COMMANDS {
+        let input = format!("@bot {command} labels");
+        match Input::new(&input, vec!["bot"]).next() {
+            Some(Command::Unknown(_)) | None => {
+                panic!("`{command}` is not taken by any command parser")
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+#[test]
+fn aliases() {
+    let aliases = HashMap::from([
+        ("postpone".to_string(), "close".to_string()),
+        ("fcp".to_string(), "ping fcp".to_string()),
+        ("FCP  merge".to_string(), "label +S-merge".to_string()),
+    ]);
+    assert_eq!(
+        expand_aliases(
+            "@bot postpone\n@bot fcp merge.\n@bot Fcp\n@bot postponed",
+            &["bot"],
+            &aliases
+        ),
+        "@bot close\n@bot label +S-merge.\n@bot ping fcp\n@bot postponed"
+    );
+    assert_eq!(
+        expand_aliases("postpone @other postpone", &["bot"], &aliases),
+        "postpone @other postpone"
+    );
+
+    let input = expand_aliases("@bot postpone", &["bot"], &aliases);
+    let mut input = Input::new(&input, vec!["bot"]);
+    assert_eq!(input.next(), Some(Command::Close(Ok(close::CloseCommand))));
+}
+
+#[test]
+fn unknown_command() {
+    let input = "thanks @bot, @bot is great";
+    let mut input = Input::new(input, vec!["bot"]);
+    assert_eq!(
+        input.next(),
+        Some(Command::Unknown(Ok(unknown::UnknownCommand {
+            word: "is".to_owned()
+        })))
+    );
+    assert_eq!(input.next(), None);
+}
+
 #[test]
 fn review_commands() {
     for (input, name) in [
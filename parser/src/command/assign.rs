@@ -42,16 +42,16 @@ impl fmt::Display for ParseError {
 impl AssignCommand {
     pub fn parse<'a>(input: &mut Tokenizer<'a>) -> Result<Option<Self>, Error<'a>> {
         let mut toks = input.clone();
-        if let Some(Token::Word("claim")) = toks.peek_token()? {
+        if toks.peek_keyword("claim")? {
             toks.next_token()?;
             if let Some(Token::Dot) | Some(Token::EndOfLine) = toks.peek_token()? {
                 toks.next_token()?;
                 *input = toks;
                 return Ok(Some(AssignCommand::Own));
             } else {
-                return Err(toks.error(ParseError::ExpectedEnd));
+                return Err(toks.error_at_next_token(ParseError::ExpectedEnd));
             }
-        } else if let Some(Token::Word("assign")) = toks.peek_token()? {
+        } else if toks.peek_keyword("assign")? {
             toks.next_token()?;
             if let Some(Token::Word(user)) = toks.next_token()? {
                 if user.starts_with('@') && user.len() != 1 {
@@ -64,14 +64,14 @@ impl AssignCommand {
             } else {
                 return Err(toks.error(ParseError::NoUser));
             }
-        } else if let Some(Token::Word("release-assignment")) = toks.peek_token()? {
+        } else if toks.peek_keyword("release-assignment")? {
             toks.next_token()?;
             if let Some(Token::Dot) | Some(Token::EndOfLine) = toks.peek_token()? {
                 toks.next_token()?;
                 *input = toks;
                 return Ok(Some(AssignCommand::Release));
             } else {
-                return Err(toks.error(ParseError::ExpectedEnd));
+                return Err(toks.error_at_next_token(ParseError::ExpectedEnd));
             }
         } else {
             return Ok(None);
@@ -1,12 +1,12 @@
 use crate::error::Error;
-use crate::token::{Token, Tokenizer};
+use crate::token::Tokenizer;
 
 #[derive(PartialEq, Eq, Debug)]
 pub struct CloseCommand;
 
 impl CloseCommand {
     pub fn parse<'a>(input: &mut Tokenizer<'a>) -> Result<Option<Self>, Error<'a>> {
-        if let Some(Token::Word("close")) = input.peek_token()? {
+        if input.peek_keyword("close")? {
             Ok(Some(Self))
         } else {
             Ok(None)
@@ -39,7 +39,7 @@ impl fmt::Display for ParseError {
 impl GlacierCommand {
     pub fn parse<'a>(input: &mut Tokenizer<'a>) -> Result<Option<GlacierCommand>, Error<'a>> {
         let mut toks = input.clone();
-        if let Some(Token::Word("glacier")) = toks.peek_token()? {
+        if toks.peek_keyword("glacier")? {
             toks.next_token()?;
             match toks.next_token()? {
                 Some(Token::Quote(s)) => {
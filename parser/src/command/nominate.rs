@@ -52,13 +52,17 @@ impl fmt::Display for ParseError {
 impl NominateCommand {
     pub fn parse<'a>(input: &mut Tokenizer<'a>) -> Result<Option<Self>, Error<'a>> {
         let mut toks = input.clone();
-        let style = match toks.peek_token()? {
-            Some(Token::Word("beta-nominate")) => Style::Beta,
-            Some(Token::Word("nominate")) => Style::Decision,
-            Some(Token::Word("beta-accept")) => Style::BetaApprove,
-            Some(Token::Word("beta-approve")) => Style::BetaApprove,
+        let word = match toks.peek_token()? {
+            Some(Token::Word(word)) => word.to_ascii_lowercase(),
             None | Some(_) => return Ok(None),
         };
+        let style = match word.as_str() {
+            "beta-nominate" => Style::Beta,
+            "nominate" => Style::Decision,
+            "beta-accept" => Style::BetaApprove,
+            "beta-approve" => Style::BetaApprove,
+            _ => return Ok(None),
+        };
         toks.next_token()?;
         let team = if style != Style::BetaApprove {
             if let Some(Token::Word(team)) = toks.next_token()? {
@@ -74,7 +78,7 @@ impl NominateCommand {
             *input = toks;
             return Ok(Some(NominateCommand { team, style }));
         } else {
-            return Err(toks.error(ParseError::ExpectedEnd));
+            return Err(toks.error_at_next_token(ParseError::ExpectedEnd));
         }
     }
 }
@@ -132,3 +136,14 @@ fn test_4() {
         Some(&ParseError::NoTeam),
     );
 }
+
+#[test]
+fn test_case_insensitive() {
+    assert_eq!(
+        parse("Beta-Nominate compiler."),
+        Ok(Some(NominateCommand {
+            team: "compiler".into(),
+            style: Style::Beta,
+        }))
+    );
+}
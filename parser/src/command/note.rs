@@ -24,12 +24,12 @@ impl fmt::Display for ParseError {
 impl NoteCommand {
     pub fn parse<'a>(input: &mut Tokenizer<'a>) -> Result<Option<Self>, Error<'a>> {
         let mut toks = input.clone();
-        if let Some(Token::Word("note")) = toks.peek_token()? {
+        if toks.peek_keyword("note")? {
             toks.next_token()?;
             let mut remove = false;
             loop {
                 match toks.next_token()? {
-                    Some(Token::Word(title)) if title == "remove" => {
+                    Some(Token::Word(title)) if title.eq_ignore_ascii_case("remove") => {
                         remove = true;
                         continue;
                     }
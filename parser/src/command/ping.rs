@@ -37,7 +37,7 @@ impl fmt::Display for ParseError {
 impl PingCommand {
     pub fn parse<'a>(input: &mut Tokenizer<'a>) -> Result<Option<Self>, Error<'a>> {
         let mut toks = input.clone();
-        if let Some(Token::Word("ping")) = toks.peek_token()? {
+        if toks.peek_keyword("ping")? {
             toks.next_token()?;
             let team = if let Some(Token::Word(team)) = toks.next_token()? {
                 team.to_owned()
@@ -49,7 +49,7 @@ impl PingCommand {
                 *input = toks;
                 return Ok(Some(PingCommand { team }));
             } else {
-                return Err(toks.error(ParseError::ExpectedEnd));
+                return Err(toks.error_at_next_token(ParseError::ExpectedEnd));
             }
         } else {
             return Ok(None);
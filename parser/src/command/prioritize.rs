@@ -2,11 +2,11 @@
 pub struct PrioritizeCommand;
 
 use crate::error::Error;
-use crate::token::{Token, Tokenizer};
+use crate::token::Tokenizer;
 
 impl PrioritizeCommand {
     pub fn parse<'a>(input: &mut Tokenizer<'a>) -> Result<Option<Self>, Error<'a>> {
-        if let Some(Token::Word("prioritize")) = input.peek_token()? {
+        if input.peek_keyword("prioritize")? {
             Ok(Some(Self))
         } else {
             Ok(None)
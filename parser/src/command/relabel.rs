@@ -89,7 +89,7 @@ impl LabelDelta {
                 delta
             }
             _ => {
-                return Err(input.error(ParseError::ExpectedLabelDelta));
+                return Err(input.error_at_next_token(ParseError::ExpectedLabelDelta));
             }
         };
         if delta.starts_with('+') {
@@ -130,10 +130,10 @@ impl RelabelCommand {
     pub fn parse<'a>(input: &mut Tokenizer<'a>) -> Result<Option<Self>, Error<'a>> {
         let mut toks = input.clone();
 
-        toks.eat_token(Token::Word("modify"))?;
+        toks.eat_keyword("modify")?;
 
-        if toks.eat_token(Token::Word("labels"))? || toks.eat_token(Token::Word("label"))? {
-            toks.eat_token(Token::Word("to"))?;
+        if toks.eat_keyword("labels")? || toks.eat_keyword("label")? {
+            toks.eat_keyword("to")?;
             toks.eat_token(Token::Colon)?;
 
             // continue
@@ -141,8 +141,8 @@ impl RelabelCommand {
             return Ok(None);
         }
 
-        if let Some(Token::Word("to")) = toks.peek_token()? {
-            return Err(toks.error(ParseError::MisleadingTo));
+        if toks.peek_keyword("to")? {
+            return Err(toks.error_at_next_token(ParseError::MisleadingTo));
         }
         // start parsing deltas
         let mut deltas = Vec::new();
@@ -151,7 +151,7 @@ impl RelabelCommand {
 
             // optional `, and` separator
             toks.eat_token(Token::Comma)?;
-            toks.eat_token(Token::Word("and"))?;
+            toks.eat_keyword("and")?;
 
             if let Some(Token::Semi) | Some(Token::Dot) | Some(Token::EndOfLine) =
                 toks.peek_token()?
@@ -273,3 +273,14 @@ fn parse_shorter_command_with_to_colon() {
         ]))
     );
 }
+
+#[test]
+fn parse_case_insensitive_keywords() {
+    assert_eq!(
+        parse("Modify Labels to: +T-compiler And -T-lang."),
+        Ok(Some(vec![
+            LabelDelta::Add(Label("T-compiler".into())),
+            LabelDelta::Remove(Label("T-lang".into())),
+        ]))
+    );
+}
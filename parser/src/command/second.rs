@@ -1,14 +1,14 @@
 use crate::error::Error;
-use crate::token::{Token, Tokenizer};
+use crate::token::Tokenizer;
 
 #[derive(PartialEq, Eq, Debug)]
 pub struct SecondCommand;
 
 impl SecondCommand {
     pub fn parse<'a>(input: &mut Tokenizer<'a>) -> Result<Option<Self>, Error<'a>> {
-        if let Some(Token::Word("second")) = input.peek_token()? {
+        if input.peek_keyword("second")? {
             Ok(Some(Self))
-        } else if let Some(Token::Word("seconded")) = input.peek_token()? {
+        } else if input.peek_keyword("seconded")? {
             Ok(Some(Self))
         } else {
             Ok(None)
@@ -42,13 +42,13 @@ impl ShortcutCommand {
 
         let mut toks = input.clone();
         if let Some(Token::Word(word)) = toks.peek_token()? {
-            if !shortcuts.contains_key(word) {
-                return Ok(None);
-            }
+            let command = match shortcuts.get(word.to_ascii_lowercase().as_str()) {
+                Some(command) => *command,
+                None => return Ok(None),
+            };
             toks.next_token()?;
             *input = toks;
-            let command = shortcuts.get(word).unwrap();
-            return Ok(Some(*command));
+            return Ok(Some(command));
         }
         Ok(None)
     }
@@ -84,3 +84,9 @@ fn test_4() {
 fn test_5() {
     assert_eq!(parse("blocked"), Ok(Some(ShortcutCommand::Blocked)));
 }
+
+#[test]
+fn test_case_insensitive() {
+    assert_eq!(parse("Ready"), Ok(Some(ShortcutCommand::Ready)));
+    assert_eq!(parse("BLOCKED"), Ok(Some(ShortcutCommand::Blocked)));
+}
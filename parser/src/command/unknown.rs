@@ -0,0 +1,189 @@
+//! Handling of words following a bot mention that no command recognizes.
+//!
+//! This is only consulted once every other command parser has declined the
+//! input. If the word is close to a known command (e.g. `prioritze`), an error
+//! suggesting the intended command is returned instead of silently ignoring
+//! the comment. A known command word which its parser declined (e.g. `modify`
+//! without `labels`) is left alone. Any other word yields an `UnknownCommand`,
+//! which the handler may answer with a gentle hint.
+
+use crate::error::Error;
+use crate::token::{Token, Tokenizer};
+use std::fmt;
+
+/// The words which start a known command. The `known_commands` test in
+/// `command.rs` checks that each of them is taken by a parser.
+pub(crate) const KNOWN_COMMANDS: &[&str] = &[
+    "assign",
+    "author",
+    "beta-accept",
+    "beta-approve",
+    "beta-nominate",
+    "blocked",
+    "claim",
+    "close",
+    "glacier",
+    "label",
+    "labels",
+    "modify",
+    "nominate",
+    "note",
+    "ping",
+    "prioritize",
+    "ready",
+    "release-assignment",
+    "review",
+    "reviewer",
+    "second",
+    "seconded",
+];
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct UnknownCommand {
+    pub word: String,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum ParseError {
+    DidYouMean {
+        word: String,
+        suggestion: &'static str,
+    },
+}
+
+impl std::error::Error for ParseError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::DidYouMean { word, suggestion } => {
+                write!(
+                    f,
+                    "unknown command `{}`, did you mean `{}`?",
+                    word, suggestion
+                )
+            }
+        }
+    }
+}
+
+impl UnknownCommand {
+    pub fn parse<'a>(input: &mut Tokenizer<'a>) -> Result<Option<Self>, Error<'a>> {
+        let mut toks = input.clone();
+        if let Some(Token::Word(word)) = toks.peek_token()? {
+            if KNOWN_COMMANDS
+                .iter()
+                .any(|command| command.eq_ignore_ascii_case(word))
+            {
+                return Ok(None);
+            }
+            if let Some(suggestion) = suggest(word) {
+                return Err(toks.error_at_next_token(ParseError::DidYouMean {
+                    word: word.to_owned(),
+                    suggestion,
+                }));
+            }
+            return Ok(Some(UnknownCommand {
+                word: word.to_owned(),
+            }));
+        }
+        Ok(None)
+    }
+}
+
+/// Finds the known command closest to `word`, if it is near enough to likely
+/// be a typo.
+fn suggest(word: &str) -> Option<&'static str> {
+    let word = word.to_ascii_lowercase();
+    // Short words are too likely to be close to a command by accident.
+    if word.len() < 3 {
+        return None;
+    }
+    let max_distance = if word.len() <= 4 { 1 } else { 2 };
+    KNOWN_COMMANDS
+        .iter()
+        .map(|command| (edit_distance(&word, command), *command))
+        .filter(|&(distance, _)| distance > 0 && distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, command)| command)
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + if ca == *cb { 0 } else { 1 };
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+fn parse<'a>(input: &'a str) -> Result<Option<UnknownCommand>, Error<'a>> {
+    let mut toks = Tokenizer::new(input);
+    Ok(UnknownCommand::parse(&mut toks)?)
+}
+
+#[test]
+fn suggests_close_command() {
+    use std::error::Error;
+    assert_eq!(
+        parse("prioritze")
+            .unwrap_err()
+            .source()
+            .unwrap()
+            .downcast_ref(),
+        Some(&ParseError::DidYouMean {
+            word: "prioritze".to_owned(),
+            suggestion: "prioritize",
+        }),
+    );
+    assert_eq!(
+        parse("Clam").unwrap_err().source().unwrap().downcast_ref(),
+        Some(&ParseError::DidYouMean {
+            word: "Clam".to_owned(),
+            suggestion: "claim",
+        }),
+    );
+}
+
+#[test]
+fn unrelated_words() {
+    assert_eq!(
+        parse("thanks"),
+        Ok(Some(UnknownCommand {
+            word: "thanks".to_owned()
+        }))
+    );
+    assert_eq!(
+        parse("is great"),
+        Ok(Some(UnknownCommand {
+            word: "is".to_owned()
+        }))
+    );
+}
+
+#[test]
+fn not_a_word() {
+    assert_eq!(parse(", please"), Ok(None));
+    assert_eq!(parse(""), Ok(None));
+}
+
+#[test]
+fn known_word() {
+    assert_eq!(parse("modify the title"), Ok(None));
+    assert_eq!(parse("Claim"), Ok(None));
+}
+
+#[test]
+fn distance() {
+    assert_eq!(edit_distance("claim", "claim"), 0);
+    assert_eq!(edit_distance("clam", "claim"), 1);
+    assert_eq!(edit_distance("lable", "label"), 2);
+    assert_eq!(edit_distance("", "note"), 4);
+}
@@ -0,0 +1,183 @@
+//! A reusable duration argument parser.
+//!
+//! The grammar is as follows:
+//!
+//! ```text
+//! Duration: Amount | `(` Amount `)`
+//! Amount: <number> <unit> | <number><unit>
+//! Unit: m | min | mins | minute | minutes
+//!     | h | hr | hrs | hour | hours
+//!     | d | day | days
+//!     | w | wk | wks | week | weeks
+//! ```
+//!
+//! For example `5 days`, `2 weeks`, `48h` and `(5 days)` are all valid
+//! durations.
+
+use crate::error::Error;
+use crate::token::{Token, Tokenizer};
+use chrono::Duration;
+use std::fmt;
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum ParseError {
+    MissingUnit,
+    UnknownUnit(String),
+    TooLarge,
+    ExpectedCloseParen,
+}
+
+impl std::error::Error for ParseError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::MissingUnit => write!(
+                f,
+                "expected a duration unit such as `days` or `hours` (e.g. `5 days`)"
+            ),
+            ParseError::UnknownUnit(unit) => write!(
+                f,
+                "unknown duration unit `{}`, expected minutes, hours, days or weeks",
+                unit
+            ),
+            ParseError::TooLarge => write!(f, "duration is too large"),
+            ParseError::ExpectedCloseParen => write!(f, "expected `)` after duration"),
+        }
+    }
+}
+
+/// Returns the length of `unit` in seconds.
+fn unit_seconds(unit: &str) -> Option<i64> {
+    match unit {
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(60),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(60 * 60),
+        "d" | "day" | "days" => Some(24 * 60 * 60),
+        "w" | "wk" | "wks" | "week" | "weeks" => Some(7 * 24 * 60 * 60),
+        _ => None,
+    }
+}
+
+/// Parses a duration such as `5 days` or `48h` from the input.
+///
+/// Returns `Ok(None)` without consuming anything if the next token does not
+/// start with a number.
+pub fn parse_duration<'a>(input: &mut Tokenizer<'a>) -> Result<Option<Duration>, Error<'a>> {
+    let mut toks = input.clone();
+    let parenthesized = toks.eat_token(Token::ParenLeft)?;
+    let word = match toks.peek_token()? {
+        Some(Token::Word(word)) if word.starts_with(|c: char| c.is_ascii_digit()) => word,
+        _ => return Ok(None),
+    };
+    let split = word
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(word.len());
+    let (number, unit) = word.split_at(split);
+    let number: i64 = match number.parse() {
+        Ok(number) => number,
+        Err(_) => return Err(toks.error_at_next_token(ParseError::TooLarge)),
+    };
+    toks.next_token()?;
+    let unit = if unit.is_empty() {
+        match toks.peek_token()? {
+            Some(Token::Word(unit)) => {
+                toks.next_token()?;
+                unit
+            }
+            _ => return Err(toks.error_at_next_token(ParseError::MissingUnit)),
+        }
+    } else {
+        unit
+    };
+    let unit_seconds = match unit_seconds(&unit.to_lowercase()) {
+        Some(seconds) => seconds,
+        None => return Err(toks.error(ParseError::UnknownUnit(unit.to_owned()))),
+    };
+    let seconds = match number.checked_mul(unit_seconds) {
+        Some(seconds) if seconds <= Duration::max_value().num_seconds() => seconds,
+        _ => return Err(toks.error(ParseError::TooLarge)),
+    };
+    if parenthesized && !toks.eat_token(Token::ParenRight)? {
+        return Err(toks.error_at_next_token(ParseError::ExpectedCloseParen));
+    }
+    *input = toks;
+    Ok(Some(Duration::seconds(seconds)))
+}
+
+#[cfg(test)]
+fn parse(input: &str) -> Result<Option<Duration>, Error<'_>> {
+    let mut toks = Tokenizer::new(input);
+    parse_duration(&mut toks)
+}
+
+#[test]
+fn separate_unit() {
+    assert_eq!(parse("5 days"), Ok(Some(Duration::days(5))));
+    assert_eq!(parse("1 day."), Ok(Some(Duration::days(1))));
+    assert_eq!(parse("2 weeks"), Ok(Some(Duration::weeks(2))));
+    assert_eq!(parse("90 Minutes"), Ok(Some(Duration::minutes(90))));
+}
+
+#[test]
+fn attached_unit() {
+    assert_eq!(parse("48h"), Ok(Some(Duration::hours(48))));
+    assert_eq!(parse("3d"), Ok(Some(Duration::days(3))));
+    assert_eq!(parse("1w"), Ok(Some(Duration::weeks(1))));
+}
+
+#[test]
+fn consumes_only_duration() {
+    let mut toks = Tokenizer::new("5 days please");
+    assert_eq!(parse_duration(&mut toks), Ok(Some(Duration::days(5))));
+    assert_eq!(toks.next_token(), Ok(Some(Token::Word("please"))));
+}
+
+#[test]
+fn parenthesized() {
+    assert_eq!(parse("(5 days)"), Ok(Some(Duration::days(5))));
+    assert_eq!(parse("( 48h )."), Ok(Some(Duration::hours(48))));
+}
+
+#[test]
+fn not_a_duration() {
+    assert_eq!(parse("days"), Ok(None));
+    assert_eq!(parse("(irreversible)"), Ok(None));
+    assert_eq!(parse(""), Ok(None));
+}
+
+#[test]
+fn errors() {
+    use std::error::Error;
+    assert_eq!(
+        parse("5").unwrap_err().source().unwrap().downcast_ref(),
+        Some(&ParseError::MissingUnit),
+    );
+    assert_eq!(
+        parse("5 fortnights")
+            .unwrap_err()
+            .source()
+            .unwrap()
+            .downcast_ref(),
+        Some(&ParseError::UnknownUnit("fortnights".to_string())),
+    );
+    assert_eq!(
+        parse("5y").unwrap_err().source().unwrap().downcast_ref(),
+        Some(&ParseError::UnknownUnit("y".to_string())),
+    );
+    assert_eq!(
+        parse("99999999999999999999 days")
+            .unwrap_err()
+            .source()
+            .unwrap()
+            .downcast_ref(),
+        Some(&ParseError::TooLarge),
+    );
+    assert_eq!(
+        parse("(5 days")
+            .unwrap_err()
+            .source()
+            .unwrap()
+            .downcast_ref(),
+        Some(&ParseError::ExpectedCloseParen),
+    );
+}
@@ -1,10 +1,15 @@
 use std::error;
 use std::fmt;
+use std::ops::Range;
 
 #[derive(Debug)]
 pub struct Error<'a> {
     pub input: &'a str,
     pub position: usize,
+    /// The byte range of the token that caused the error.
+    ///
+    /// This is empty if the error occurred at the end of a line or the input.
+    pub span: Range<usize>,
     pub source: Box<dyn error::Error + Send>,
 }
 
@@ -24,6 +29,16 @@ impl<'a> Error<'a> {
     pub fn position(&self) -> usize {
         self.position
     }
+
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// The fragment of the input that caused the error, e.g. to quote it back
+    /// to the user.
+    pub fn fragment(&self) -> &'a str {
+        &self.input[self.span.clone()]
+    }
 }
 
 impl<'a> fmt::Display for Error<'a> {
@@ -1,4 +1,5 @@
 pub mod command;
+pub mod duration;
 pub mod error;
 mod ignore_block;
 mod mentions;
@@ -1,6 +1,7 @@
 use crate::error::Error;
 use std::fmt;
 use std::iter::Peekable;
+use std::ops::Range;
 use std::str::CharIndices;
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -41,6 +42,9 @@ pub struct Tokenizer<'a> {
     input: &'a str,
     chars: Peekable<CharIndices<'a>>,
     end_of_input_emitted: bool,
+    /// The byte range of the last token consumed, or of the erroneous token
+    /// that couldn't be.
+    last_token: Range<usize>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -82,17 +86,47 @@ impl<'a> Tokenizer<'a> {
             input,
             chars: input.char_indices().peekable(),
             end_of_input_emitted: false,
+            last_token: 0..0,
         }
     }
 
+    /// Creates an error about the token that was just consumed.
     pub fn error<T: 'static + std::error::Error + Send>(&mut self, source: T) -> Error<'a> {
+        let span = self.last_token.clone();
+        self.error_spanning(span, source)
+    }
+
+    /// Creates an error about the next token, for parsers which reject a
+    /// token without consuming it.
+    pub fn error_at_next_token<T: 'static + std::error::Error + Send>(
+        &mut self,
+        source: T,
+    ) -> Error<'a> {
+        let mut next = self.clone();
+        let _ = next.next_token();
+        self.error_spanning(next.last_token, source)
+    }
+
+    fn error_spanning<T: 'static + std::error::Error + Send>(
+        &mut self,
+        span: Range<usize>,
+        source: T,
+    ) -> Error<'a> {
         Error {
             input: self.input,
             position: self.cur_pos(),
+            span,
             source: Box::new(source),
         }
     }
 
+    /// Creates an error about the token starting at `start` which is being
+    /// consumed. It extends as far as the tokenizer got.
+    fn token_error(&mut self, start: usize, kind: ErrorKind) -> Error<'a> {
+        self.last_token = start..self.cur_pos();
+        self.error(kind)
+    }
+
     fn consume_whitespace(&mut self) {
         while self
             .cur()
@@ -146,18 +180,17 @@ impl<'a> Tokenizer<'a> {
     }
 
     fn consume_string(&mut self) -> Result<Option<Token<'a>>, Error<'a>> {
-        if let Some((_, '"')) = self.cur() {
-            // okay
-        } else {
-            return Ok(None);
-        }
+        let quote = match self.cur() {
+            Some((pos, '"')) => pos,
+            _ => return Ok(None),
+        };
         self.advance(); // eat "
         let start = self.cur_pos();
         loop {
             match self.cur() {
                 Some((_, '"')) => break,
                 Some(_) => self.advance(),
-                None => return Err(self.error(ErrorKind::UnterminatedString)),
+                None => return Err(self.token_error(quote, ErrorKind::UnterminatedString)),
             };
         }
         let body = self.str_from(start);
@@ -175,6 +208,17 @@ impl<'a> Tokenizer<'a> {
 
     pub fn next_token(&mut self) -> Result<Option<Token<'a>>, Error<'a>> {
         self.consume_whitespace();
+        let start = self.cur_pos();
+        self.last_token = start..start;
+        let token = self.consume_token()?;
+        // The end of a line has nothing to quote.
+        if token != Some(Token::EndOfLine) {
+            self.last_token = start..self.cur_pos();
+        }
+        Ok(token)
+    }
+
+    fn consume_token(&mut self) -> Result<Option<Token<'a>>, Error<'a>> {
         if self.at_end() {
             if self.end_of_input_emitted {
                 return Ok(None);
@@ -200,9 +244,9 @@ impl<'a> Tokenizer<'a> {
             if self.cur().unwrap().1 == '"' {
                 let so_far = self.str_from(start);
                 if so_far.starts_with('r') && so_far.chars().skip(1).all(|v| v == '#' || v == '"') {
-                    return Err(self.error(ErrorKind::RawString));
+                    return Err(self.token_error(start, ErrorKind::RawString));
                 } else {
-                    return Err(self.error(ErrorKind::QuoteInWord));
+                    return Err(self.token_error(start, ErrorKind::QuoteInWord));
                 }
             }
             self.advance();
@@ -219,6 +263,43 @@ impl<'a> Tokenizer<'a> {
             _ => Ok(false),
         }
     }
+
+    /// Checks whether the next token is the word `keyword`, ignoring ASCII case.
+    ///
+    /// `keyword` is expected to be lowercase.
+    pub fn peek_keyword(&mut self, keyword: &str) -> Result<bool, Error<'a>> {
+        match self.peek_token()? {
+            Some(Token::Word(word)) => Ok(word.eq_ignore_ascii_case(keyword)),
+            _ => Ok(false),
+        }
+    }
+
+    /// Consumes the next token if it is the word `keyword`, ignoring ASCII case.
+    pub fn eat_keyword(&mut self, keyword: &str) -> Result<bool, Error<'a>> {
+        if self.peek_keyword(keyword)? {
+            self.next_token()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Consumes a trailing `: free text` up to (but not including) the end of
+    /// the line, returning the trimmed text after the colon.
+    ///
+    /// This lets commands such as `hold: this breaks coherence` carry a reason
+    /// without the text being tokenized. Returns `None` and consumes nothing
+    /// if the next token is not a colon.
+    pub fn eat_reason(&mut self) -> Result<Option<&'a str>, Error<'a>> {
+        if !self.eat_token(Token::Colon)? {
+            return Ok(None);
+        }
+        let start = self.cur_pos();
+        while self.cur().map_or(false, |(_, ch)| ch != '\n') {
+            self.advance();
+        }
+        Ok(Some(self.str_from(start).trim()))
+    }
 }
 
 #[cfg(test)]
@@ -314,6 +395,19 @@ fn tokenize_5() {
     );
 }
 
+#[test]
+fn tokenize_quoted_phrase() {
+    assert_eq!(
+        tokenize(r#"concern "unsound interaction with Pin"."#).unwrap(),
+        [
+            Token::Word("concern"),
+            Token::Quote("unsound interaction with Pin"),
+            Token::Dot,
+            Token::EndOfLine,
+        ]
+    );
+}
+
 #[test]
 fn tokenize_6() {
     assert_eq!(
@@ -349,3 +443,71 @@ fn tokenize_raw_string_prohibit_1() {
         (18, ErrorKind::QuoteInWord)
     );
 }
+
+#[test]
+fn tokenize_reason() {
+    let mut toks = Tokenizer::new(
+        r#"hold: this "breaks" coherence.
+next"#,
+    );
+    assert_eq!(toks.next_token().unwrap(), Some(Token::Word("hold")));
+    assert_eq!(
+        toks.eat_reason().unwrap(),
+        Some(r#"this "breaks" coherence."#)
+    );
+    assert_eq!(toks.next_token().unwrap(), Some(Token::EndOfLine));
+    assert_eq!(toks.next_token().unwrap(), Some(Token::Word("next")));
+}
+
+#[test]
+fn tokenize_no_reason() {
+    let mut toks = Tokenizer::new("hold.");
+    assert_eq!(toks.next_token().unwrap(), Some(Token::Word("hold")));
+    assert_eq!(toks.eat_reason().unwrap(), None);
+    assert_eq!(toks.next_token().unwrap(), Some(Token::Dot));
+}
+
+#[test]
+fn tokenize_keywords() {
+    let mut toks = Tokenizer::new("Merge HOLD merged");
+    assert!(!toks.peek_keyword("hold").unwrap());
+    assert!(toks.eat_keyword("merge").unwrap());
+    assert!(toks.eat_keyword("hold").unwrap());
+    assert!(!toks.eat_keyword("merge").unwrap());
+    assert_eq!(toks.next_token().unwrap(), Some(Token::Word("merged")));
+}
+
+#[test]
+fn error_span() {
+    let mut toks = Tokenizer::new("label +bug  extra words");
+    toks.next_token().unwrap();
+    toks.next_token().unwrap();
+    let err = toks.error(ErrorKind::QuoteInWord);
+    assert_eq!(err.position(), 10);
+    assert_eq!(err.span(), 6..10);
+    assert_eq!(err.fragment(), "+bug");
+    let err = toks.error_at_next_token(ErrorKind::QuoteInWord);
+    assert_eq!(err.position(), 10);
+    assert_eq!(err.fragment(), "extra");
+
+    let mut toks = Tokenizer::new("label\n");
+    toks.next_token().unwrap();
+    assert_eq!(
+        toks.error_at_next_token(ErrorKind::QuoteInWord).fragment(),
+        ""
+    );
+}
+
+#[test]
+fn tokenize_error_span() {
+    assert_eq!(
+        tokenize(r#"wordy wordy word"quoteno"#)
+            .unwrap_err()
+            .fragment(),
+        "word"
+    );
+    assert_eq!(
+        tokenize(r#"a "testing"#).unwrap_err().fragment(),
+        r#""testing"#
+    );
+}
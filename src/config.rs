@@ -36,6 +36,8 @@ pub(crate) struct Config {
     pub(crate) note: Option<NoteConfig>,
     pub(crate) mentions: Option<MentionsConfig>,
     pub(crate) no_merges: Option<NoMergesConfig>,
+    pub(crate) command_aliases: Option<CommandAliasesConfig>,
+    pub(crate) unknown_command: Option<UnknownCommandConfig>,
     // We want this validation to run even without the entry in the config file
     #[serde(default = "ValidateConfig::default")]
     pub(crate) validate_config: Option<ValidateConfig>,
@@ -128,6 +130,13 @@ pub(crate) struct NoMergesConfig {
     pub(crate) message: Option<String>,
 }
 
+#[derive(PartialEq, Eq, Debug, serde::Deserialize)]
+pub(crate) struct CommandAliasesConfig {
+    // alias -> command it expands to, e.g. `"fcp merge" = "merge"`
+    #[serde(flatten)]
+    pub(crate) aliases: HashMap<String, String>,
+}
+
 #[derive(PartialEq, Eq, Debug, serde::Deserialize)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct NoteConfig {
@@ -135,6 +144,13 @@ pub(crate) struct NoteConfig {
     _empty: (),
 }
 
+#[derive(PartialEq, Eq, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct UnknownCommandConfig {
+    #[serde(default)]
+    _empty: (),
+}
+
 #[derive(PartialEq, Eq, Debug, serde::Deserialize)]
 pub(crate) struct MentionsConfig {
     #[serde(flatten)]
@@ -462,8 +478,29 @@ mod tests {
                 review_requested: None,
                 mentions: None,
                 no_merges: None,
+                command_aliases: None,
+                unknown_command: None,
                 validate_config: Some(ValidateConfig {}),
             }
         );
     }
+
+    #[test]
+    fn command_aliases() {
+        let config = r#"
+            [command-aliases]
+            "fcp close" = "close"
+            postpone = "close"
+        "#;
+        let config = toml::from_str::<Config>(&config).unwrap();
+        assert_eq!(
+            config.command_aliases,
+            Some(CommandAliasesConfig {
+                aliases: HashMap::from([
+                    ("fcp close".to_owned(), "close".to_owned()),
+                    ("postpone".to_owned(), "close".to_owned()),
+                ]),
+            })
+        );
+    }
 }
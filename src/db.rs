@@ -1,14 +1,24 @@
-use crate::{db::jobs::*, handlers::Context, jobs::jobs};
+use crate::{
+    db::jobs::*,
+    handlers::Context,
+    jobs::{
+        jobs, jobs_shutting_down, retry_delay, scheduling_horizon, MisfirePolicy,
+        JOB_LOCK_DURATION_IN_SECS, JOB_MAX_JITTER_IN_MILLIS, JOB_MAX_PER_PASS,
+        JOB_MISFIRE_THRESHOLD_IN_SECS,
+    },
+};
 use anyhow::Context as _;
 use chrono::Utc;
 use native_tls::{Certificate, TlsConnector};
 use postgres_native_tls::MakeTlsConnector;
+use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio_postgres::Client as DbClient;
 
 pub mod issue_data;
 pub mod jobs;
+pub mod jobs_history;
 pub mod notifications;
 pub mod rustc_commits;
 
@@ -23,6 +33,8 @@ lazy_static::lazy_static! {
             .expect("failed to get RDS cert");
          resp.bytes().expect("failed to get RDS cert body").to_vec()
     };
+    /// The jobs claimed by this instance which haven't finished yet.
+    static ref CLAIMED_JOBS: Mutex<HashSet<uuid::Uuid>> = Mutex::new(HashSet::new());
 }
 
 pub struct ClientPool {
@@ -199,12 +211,34 @@ pub async fn run_migrations(client: &DbClient) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Queues the runs of `jobs` up to `scheduling_horizon`, or at least the
+/// next run of each.
 pub async fn schedule_jobs(db: &DbClient, jobs: Vec<JobSchedule>) -> anyhow::Result<()> {
+    let until = scheduling_horizon(Utc::now());
     for job in jobs {
-        let mut upcoming = job.schedule.upcoming(Utc).take(1);
+        let mut upcoming = job.schedule.upcoming(Utc);
+        let next = upcoming.next();
+        for scheduled_at in next
+            .into_iter()
+            .chain(upcoming.take_while(|scheduled_at| *scheduled_at <= until))
+        {
+            schedule_job(db, job.name, job.metadata.clone(), scheduled_at).await?;
+        }
+    }
+
+    Ok(())
+}
 
-        if let Some(scheduled_at) = upcoming.next() {
-            schedule_job(db, job.name, job.metadata, scheduled_at).await?;
+/// Cancels the runs of periodic jobs which were queued before the jobs were
+/// disabled.
+pub async fn cancel_disabled_jobs(
+    db: &DbClient,
+    disabled: &[(&str, serde_json::Value)],
+) -> anyhow::Result<()> {
+    for (name, metadata) in disabled {
+        let cancelled = delete_jobs_matching(db, name, metadata).await?;
+        if cancelled > 0 {
+            tracing::info!("cancelled {} runs of disabled job `{}`", cancelled, name);
         }
     }
 
@@ -218,33 +252,229 @@ pub async fn schedule_job(
     when: chrono::DateTime<Utc>,
 ) -> anyhow::Result<()> {
     let all_jobs = jobs();
-    if !all_jobs.iter().any(|j| j.name() == job_name) {
+    let Some(job) = all_jobs.iter().find(|j| j.name() == job_name) else {
         anyhow::bail!("Job {} does not exist in the current job list.", job_name);
-    }
+    };
 
     if let Err(_) = get_job_by_name_and_scheduled_at(&db, job_name, &when).await {
         // mean there's no job already in the db with that name and scheduled_at
-        insert_job(&db, job_name, &when, &job_metadata).await?;
+        insert_job(&db, job_name, &when, &job_metadata, job.priority()).await?;
     }
 
     Ok(())
 }
 
-pub async fn run_scheduled_jobs(ctx: &Context, db: &DbClient) -> anyhow::Result<()> {
-    let jobs = get_jobs_to_execute(&db).await.unwrap();
+/// Like `schedule_job`, but moves an already pending job with the same name
+/// and metadata to `when` instead of scheduling it a second time.
+pub async fn reschedule_job(
+    db: &DbClient,
+    job_name: &str,
+    job_metadata: serde_json::Value,
+    when: chrono::DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let all_jobs = jobs();
+    let Some(job) = all_jobs.iter().find(|j| j.name() == job_name) else {
+        anyhow::bail!("Job {} does not exist in the current job list.", job_name);
+    };
+
+    upsert_job(&db, job_name, &when, &job_metadata, job.priority()).await
+}
+
+/// Claims the jobs which are due and runs each of them on its own task, as
+/// long as one of the `workers` permits is free. Jobs still running from an
+/// earlier pass hold on to their permit, so a slow job only takes up one
+/// worker instead of holding up everything behind it.
+pub async fn run_scheduled_jobs(
+    ctx: &Arc<Context>,
+    db: &DbClient,
+    workers: &Arc<Semaphore>,
+) -> anyhow::Result<()> {
+    let available = workers.available_permits() as i64;
+    if available == 0 {
+        tracing::trace!("all job workers are busy");
+        return Ok(());
+    }
+    let jobs = claim_jobs_to_execute(
+        &db,
+        std::time::Duration::from_secs(JOB_LOCK_DURATION_IN_SECS),
+        JOB_MAX_PER_PASS.min(available),
+    )
+    .await
+    .unwrap();
     tracing::trace!("jobs to execute: {:#?}", jobs);
+    CLAIMED_JOBS
+        .lock()
+        .unwrap()
+        .extend(jobs.iter().map(|job| job.id));
+
+    for (idx, job) in jobs.into_iter().enumerate() {
+        // The remaining jobs are released by `drain_jobs`.
+        if jobs_shutting_down() {
+            break;
+        }
+        if idx > 0 {
+            // Spread out jobs which are due at the same time.
+            let jitter =
+                rand::Rng::gen_range(&mut rand::thread_rng(), 0..=JOB_MAX_JITTER_IN_MILLIS);
+            tokio::time::sleep(std::time::Duration::from_millis(jitter)).await;
+        }
+
+        let permit = workers.clone().acquire_owned().await?;
+        // The bot may have started shutting down while we waited for a worker.
+        if jobs_shutting_down() {
+            break;
+        }
+        let ctx = ctx.clone();
+        tokio::task::spawn(async move {
+            let db = ctx.db.get().await;
+            if let Err(e) = run_job(&ctx, &db, &job).await {
+                tracing::error!("failed to run job (id={}): {:?}", job.id, e);
+            }
+            CLAIMED_JOBS.lock().unwrap().remove(&job.id);
+            drop(permit);
+        });
+    }
+
+    Ok(())
+}
+
+/// Waits up to `grace_period` for the running jobs to finish once the job
+/// runner stopped claiming new ones, then releases the claims on the jobs
+/// which didn't, so that another instance runs them again right away instead
+/// of after `JOB_LOCK_DURATION_IN_SECS`.
+pub async fn drain_jobs(
+    db: &DbClient,
+    workers: &Semaphore,
+    concurrency: usize,
+    grace_period: std::time::Duration,
+) -> anyhow::Result<()> {
+    let running = concurrency - workers.available_permits();
+    if running > 0 {
+        tracing::info!("waiting for {} running jobs to finish", running);
+    }
+    // Holding on to every permit until the claims are released keeps the
+    // runner from starting any of the unfinished jobs in the meantime.
+    let permits =
+        tokio::time::timeout(grace_period, workers.acquire_many(concurrency as u32)).await;
+    if permits.is_err() {
+        tracing::warn!("jobs still running after {:?}", grace_period);
+    }
 
-    for job in jobs.iter() {
-        update_job_executed_at(&db, &job.id).await?;
+    let unfinished: Vec<_> = CLAIMED_JOBS.lock().unwrap().iter().copied().collect();
+    if !unfinished.is_empty() {
+        tracing::warn!("releasing unfinished jobs: {:?}", unfinished);
+        release_jobs(db, &unfinished).await?;
+    }
+    drop(permits);
+    Ok(())
+}
 
-        match handle_job(&ctx, &job.name, &job.metadata).await {
-            Ok(_) => {
-                tracing::trace!("job successfully executed (id={})", job.id);
+async fn run_job(ctx: &Context, db: &DbClient, job: &Job) -> anyhow::Result<()> {
+    let mut metadata = job.metadata.clone();
+    let overdue =
+        Utc::now() - job.scheduled_at > chrono::Duration::seconds(JOB_MISFIRE_THRESHOLD_IN_SECS);
+    // Retries are late by design, so only the first attempt can misfire.
+    if overdue && job.attempts == 0 {
+        match misfire_policy(&job.name) {
+            MisfirePolicy::Run => {}
+            MisfirePolicy::Skip => {
+                tracing::warn!(
+                    "skipping overdue job (id={}, scheduled_at={})",
+                    job.id,
+                    job.scheduled_at
+                );
                 delete_job(&db, &job.id).await?;
+                return Ok(());
+            }
+            MisfirePolicy::RunLate => {
+                if let Some(object) = metadata.as_object_mut() {
+                    object.insert("late".to_string(), serde_json::Value::Bool(true));
+                }
+            }
+        }
+    }
+
+    update_job_executed_at(&db, &job.id).await?;
+
+    let started_at = Utc::now();
+    let start = std::time::Instant::now();
+    let result = handle_job(&ctx, &job.name, &metadata).await;
+    crate::jobs::metrics::record_execution(&job.name, start.elapsed(), result.is_ok());
+    let execution = jobs_history::JobExecution {
+        job_id: job.id,
+        name: job.name.clone(),
+        metadata,
+        started_at,
+        duration_ms: start.elapsed().as_millis() as i64,
+        succeeded: result.is_ok(),
+        error_message: result.as_ref().err().map(|e| format!("{:?}", e)),
+        output: result.as_ref().ok().filter(|o| !o.is_null()).cloned(),
+    };
+    if let Err(e) = jobs_history::record_job_execution(&db, &execution).await {
+        tracing::error!("failed to record job execution (id={}): {:?}", job.id, e);
+    }
+
+    match result {
+        Ok(_) => {
+            tracing::trace!("job successfully executed (id={})", job.id);
+            delete_job(&db, &job.id).await?;
+        }
+        Err(e) => {
+            tracing::error!("job failed on execution (id={:?}, error={:?})", job.id, e);
+            let retry_at = retry_delay(job.attempts + 1).map(|delay| Utc::now() + delay);
+            record_job_failure(&db, &job.id, &e.to_string(), retry_at).await?;
+            if retry_at.is_none() {
+                move_job_to_dead_letter(&db, &job.id).await?;
+                alert_dead_job(ctx, job, &e).await;
             }
+        }
+    }
+
+    Ok(())
+}
+
+/// Queues the runs of every recurring schedule stored in the database up to
+/// `scheduling_horizon`, or at least the next run of each.
+///
+/// Invalid schedules are logged and skipped so that they don't hold up the
+/// others. So are runs which would fall at the same time as another job of
+/// the same name with different metadata, as only one job of a name can be
+/// scheduled at a time.
+pub async fn schedule_stored_jobs(db: &DbClient) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let until = scheduling_horizon(now);
+    for schedule in get_job_schedules(db).await? {
+        let runs = match schedule.runs_until(now, until) {
+            Ok(runs) => runs,
             Err(e) => {
-                tracing::error!("job failed on execution (id={:?}, error={:?})", job.id, e);
-                update_job_error_message(&db, &job.id, &e.to_string()).await?;
+                tracing::error!("invalid job schedule (id={}, error={:?})", schedule.id, e);
+                continue;
+            }
+        };
+        for scheduled_at in runs {
+            if let Ok(existing) =
+                get_job_by_name_and_scheduled_at(db, &schedule.name, &scheduled_at).await
+            {
+                if existing.metadata != schedule.metadata {
+                    tracing::error!(
+                        "job schedule collides with another job, skipping it (id={}, name={}, scheduled_at={}, metadata={}, other_metadata={})",
+                        schedule.id,
+                        schedule.name,
+                        scheduled_at,
+                        schedule.metadata,
+                        existing.metadata
+                    );
+                    continue;
+                }
+            }
+            if let Err(e) =
+                schedule_job(db, &schedule.name, schedule.metadata.clone(), scheduled_at).await
+            {
+                tracing::error!(
+                    "failed to schedule stored job (id={}, error={:?})",
+                    schedule.id,
+                    e
+                );
             }
         }
     }
@@ -252,15 +482,68 @@ pub async fn run_scheduled_jobs(ctx: &Context, db: &DbClient) -> anyhow::Result<
     Ok(())
 }
 
+/// Reports a job that was moved to the dead letter queue, in the logs and, if
+/// `TRIAGEBOT_JOB_ALERT_STREAM` is set, on Zulip.
+async fn alert_dead_job(ctx: &Context, job: &Job, error: &anyhow::Error) {
+    tracing::error!(
+        "job failed too many times, moved to dead letter queue (id={}, name={}, attempts={}, metadata={}, error={:?})",
+        job.id,
+        job.name,
+        job.attempts + 1,
+        job.metadata,
+        error
+    );
+
+    let Some(stream) = std::env::var("TRIAGEBOT_JOB_ALERT_STREAM")
+        .ok()
+        .and_then(|s| s.parse().ok())
+    else {
+        return;
+    };
+    let message = format!(
+        "Job `{}` (id `{}`) failed {} times and was moved to the dead letter queue.\n\n\
+        Metadata: `{}`\n\nLast error: {}",
+        job.name,
+        job.id,
+        job.attempts + 1,
+        job.metadata,
+        error
+    );
+    let zulip_req = crate::zulip::MessageApiRequest {
+        recipient: crate::zulip::Recipient::Stream {
+            id: stream,
+            topic: "failed jobs",
+        },
+        content: &message,
+    };
+    if let Err(e) = zulip_req.send(&ctx.github.raw()).await {
+        tracing::error!("failed to send dead job alert to zulip: {:?}", e);
+    }
+}
+
+fn misfire_policy(name: &str) -> MisfirePolicy {
+    jobs()
+        .iter()
+        .find(|job| job.name() == name)
+        .map_or(MisfirePolicy::Run, |job| job.misfire_policy())
+}
+
 // Try to handle a specific job
 async fn handle_job(
     ctx: &Context,
     name: &String,
     metadata: &serde_json::Value,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<serde_json::Value> {
     for job in jobs() {
         if &job.name() == &name {
-            return job.run(ctx, metadata).await;
+            let timeout = job.timeout();
+            return match tokio::time::timeout(timeout, job.run(ctx, metadata)).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow::anyhow!(
+                    "job timed out after {} seconds",
+                    timeout.as_secs()
+                )),
+            };
         }
     }
     tracing::trace!(
@@ -269,7 +552,7 @@ async fn handle_job(
         metadata
     );
 
-    Ok(())
+    Ok(serde_json::Value::Null)
 }
 
 static MIGRATIONS: &[&str] = &[
@@ -334,4 +617,49 @@ CREATE table review_prefs (
     "
 CREATE UNIQUE INDEX review_prefs_user_id ON review_prefs(user_id);
  ",
+    "
+CREATE TABLE job_schedules (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    name TEXT NOT NULL,
+    cron TEXT,
+    interval_secs BIGINT,
+    metadata JSONB NOT NULL DEFAULT 'null',
+    CHECK ((cron IS NULL) <> (interval_secs IS NULL))
+);
+",
+    "ALTER TABLE jobs ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE jobs ADD COLUMN retry_at TIMESTAMP WITH TIME ZONE;",
+    "ALTER TABLE jobs ADD COLUMN failed_at TIMESTAMP WITH TIME ZONE;",
+    "
+CREATE TABLE jobs_history (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    job_id UUID NOT NULL,
+    name TEXT NOT NULL,
+    metadata JSONB,
+    started_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    succeeded BOOLEAN NOT NULL,
+    error_message TEXT
+);
+",
+    "CREATE INDEX jobs_history_name_started_at_index ON jobs_history (name, started_at);",
+    "ALTER TABLE jobs ADD COLUMN locked_until TIMESTAMP WITH TIME ZONE;",
+    "
+CREATE TABLE jobs_dead_letter (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    scheduled_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    metadata JSONB,
+    attempts INTEGER NOT NULL,
+    error_message TEXT,
+    failed_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+",
+    "ALTER TABLE jobs ADD COLUMN priority SMALLINT NOT NULL DEFAULT 0;",
+    "ALTER TABLE jobs_history ADD COLUMN output JSONB;",
+    "
+WITH dead AS (DELETE FROM jobs WHERE failed_at IS NOT NULL RETURNING *)
+INSERT INTO jobs_dead_letter (id, name, scheduled_at, metadata, attempts, error_message, failed_at)
+    SELECT id, name, scheduled_at, metadata, attempts, error_message, failed_at FROM dead;
+",
 ];
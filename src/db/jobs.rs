@@ -1,11 +1,16 @@
 //! The `jobs` table provides a way to have scheduled jobs
+//!
+//! The `job_schedules` table holds recurring schedules which the scheduler
+//! turns into `jobs` entries, in addition to the built-in `default_jobs`.
 use anyhow::{Context as _, Result};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use cron::Schedule;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use tokio_postgres::Client as DbClient;
 use uuid::Uuid;
 
+#[derive(Clone)]
 pub struct JobSchedule {
     pub name: &'static str,
     pub schedule: Schedule,
@@ -20,6 +25,67 @@ pub struct Job {
     pub metadata: serde_json::Value,
     pub executed_at: Option<DateTime<Utc>>,
     pub error_message: Option<String>,
+    /// How many times the job has failed so far.
+    pub attempts: i32,
+    /// When the job may be retried after a failure.
+    pub retry_at: Option<DateTime<Utc>>,
+    /// Set once the job has failed too many times to be retried, just before
+    /// it is moved to the dead letter queue.
+    pub failed_at: Option<DateTime<Utc>>,
+    /// Jobs with a higher priority are run first.
+    pub priority: i16,
+}
+
+/// A recurring schedule stored in the database, repeating either according to
+/// a cron expression or at a fixed interval.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StoredJobSchedule {
+    pub id: Uuid,
+    pub name: String,
+    pub cron: Option<String>,
+    pub interval_secs: Option<i64>,
+    pub metadata: serde_json::Value,
+}
+
+impl StoredJobSchedule {
+    /// The first time after `now` at which the job should run.
+    pub fn next_run(&self, now: DateTime<Utc>) -> Result<Option<DateTime<Utc>>> {
+        match (&self.cron, self.interval_secs) {
+            (Some(cron), None) => {
+                let schedule = Schedule::from_str(cron)
+                    .with_context(|| format!("invalid cron expression `{cron}`"))?;
+                Ok(schedule.after(&now).next())
+            }
+            (None, Some(secs)) if secs > 0 => {
+                // Align runs to multiples of the interval, so that each pass of
+                // the scheduler picks the same time until it has passed.
+                let next = (now.timestamp() / secs + 1) * secs;
+                Ok(Utc.timestamp_opt(next, 0).single())
+            }
+            _ => anyhow::bail!(
+                "job schedule {} needs either a cron expression or a positive interval",
+                self.id
+            ),
+        }
+    }
+
+    /// Every run after `now` up to `until`, or at least the next one.
+    pub fn runs_until(
+        &self,
+        now: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>> {
+        let mut runs = Vec::new();
+        let mut after = now;
+        while let Some(run) = self.next_run(after)? {
+            if run > until && !runs.is_empty() {
+                break;
+            }
+            runs.push(run);
+            after = run;
+        }
+        Ok(runs)
+    }
 }
 
 pub async fn insert_job(
@@ -27,13 +93,14 @@ pub async fn insert_job(
     name: &str,
     scheduled_at: &DateTime<Utc>,
     metadata: &serde_json::Value,
+    priority: i16,
 ) -> Result<()> {
     tracing::trace!("insert_job(name={})", name);
 
     db.execute(
-        "INSERT INTO jobs (name, scheduled_at, metadata) VALUES ($1, $2, $3) 
+        "INSERT INTO jobs (name, scheduled_at, metadata, priority) VALUES ($1, $2, $3, $4) 
             ON CONFLICT (name, scheduled_at) DO UPDATE SET metadata = EXCLUDED.metadata",
-        &[&name, &scheduled_at, &metadata],
+        &[&name, &scheduled_at, &metadata, &priority],
     )
     .await
     .context("Inserting job")?;
@@ -41,6 +108,51 @@ pub async fn insert_job(
     Ok(())
 }
 
+/// Schedules a job at `scheduled_at`, replacing any pending job with the same
+/// name and metadata rather than adding a second one.
+pub async fn upsert_job(
+    db: &DbClient,
+    name: &str,
+    scheduled_at: &DateTime<Utc>,
+    metadata: &serde_json::Value,
+    priority: i16,
+) -> Result<()> {
+    tracing::trace!("upsert_job(name={})", name);
+
+    db.execute(
+        "WITH replaced AS (
+            DELETE FROM jobs
+                WHERE name = $1 AND metadata = $3 AND scheduled_at <> $2
+        )
+        INSERT INTO jobs (name, scheduled_at, metadata, priority) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (name, scheduled_at) DO UPDATE SET metadata = EXCLUDED.metadata,
+                error_message = NULL, attempts = 0, retry_at = NULL",
+        &[&name, &scheduled_at, &metadata, &priority],
+    )
+    .await
+    .context("Upserting job")?;
+
+    Ok(())
+}
+
+/// Makes a pending job due immediately, clearing any failed attempts. Returns
+/// whether the job exists; jobs in the dead letter queue don't count.
+pub async fn run_job_now(db: &DbClient, id: &Uuid) -> Result<bool> {
+    tracing::trace!("run_job_now(id={})", id);
+
+    let updated = db
+        .execute(
+            "UPDATE jobs SET scheduled_at = now(), retry_at = NULL, locked_until = NULL,
+                attempts = 0
+            WHERE id = $1",
+            &[&id],
+        )
+        .await
+        .context("Making job due now")?;
+
+    Ok(updated > 0)
+}
+
 pub async fn delete_job(db: &DbClient, id: &Uuid) -> Result<()> {
     tracing::trace!("delete_job(id={})", id);
 
@@ -51,15 +163,92 @@ pub async fn delete_job(db: &DbClient, id: &Uuid) -> Result<()> {
     Ok(())
 }
 
-pub async fn update_job_error_message(db: &DbClient, id: &Uuid, message: &String) -> Result<()> {
-    tracing::trace!("update_job_error_message(id={})", id);
+/// Cancels the pending jobs named `name` whose metadata contains `metadata`
+/// (e.g. `{"issue": 123}` matches all jobs for that issue), returning how
+/// many were deleted.
+pub async fn delete_jobs_matching(
+    db: &DbClient,
+    name: &str,
+    metadata: &serde_json::Value,
+) -> Result<u64> {
+    tracing::trace!("delete_jobs_matching(name={}, metadata={})", name, metadata);
+
+    let deleted = db
+        .execute(
+            "DELETE FROM jobs WHERE name = $1 AND metadata @> $2",
+            &[&name, &metadata],
+        )
+        .await
+        .context("Deleting matching jobs")?;
+
+    Ok(deleted)
+}
+
+/// Moves the pending job named `name` with exactly `metadata` to
+/// `scheduled_at`, keeping its id and metadata, and clears any pending retry.
+/// A job that is currently running is left alone. If several jobs match, the
+/// one due first is moved. Returns whether a job was moved.
+///
+/// Fails if another job with the same name is already scheduled at
+/// `scheduled_at`.
+pub async fn update_job_scheduled_at(
+    db: &DbClient,
+    name: &str,
+    metadata: &serde_json::Value,
+    scheduled_at: &DateTime<Utc>,
+) -> Result<bool> {
+    tracing::trace!(
+        "update_job_scheduled_at(name={}, metadata={}, scheduled_at={})",
+        name,
+        metadata,
+        scheduled_at
+    );
+
+    let updated = db
+        .execute(
+            "UPDATE jobs SET scheduled_at = $3, retry_at = NULL
+            WHERE id = (
+                SELECT id FROM jobs
+                WHERE name = $1 AND metadata = $2
+                    AND (locked_until IS NULL OR locked_until <= now())
+                ORDER BY scheduled_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )",
+            &[&name, &metadata, &scheduled_at],
+        )
+        .await;
+    match updated {
+        Ok(updated) => Ok(updated > 0),
+        Err(e) if e.code() == Some(&tokio_postgres::error::SqlState::UNIQUE_VIOLATION) => {
+            anyhow::bail!(
+                "another {} job is already scheduled at {}",
+                name,
+                scheduled_at
+            )
+        }
+        Err(e) => Err(e).context("Updating job scheduled at"),
+    }
+}
+
+/// Records a failed attempt at running a job. The job is retried at
+/// `retry_at`, or marked as failed for good if that is `None`.
+pub async fn record_job_failure(
+    db: &DbClient,
+    id: &Uuid,
+    message: &String,
+    retry_at: Option<DateTime<Utc>>,
+) -> Result<()> {
+    tracing::trace!("record_job_failure(id={})", id);
 
     db.execute(
-        "UPDATE jobs SET error_message = $2 WHERE id = $1",
-        &[&id, &message],
+        "UPDATE jobs SET error_message = $2, attempts = attempts + 1, retry_at = $3,
+            failed_at = CASE WHEN $3::TIMESTAMPTZ IS NULL THEN now() END, locked_until = NULL
+            WHERE id = $1",
+        &[&id, &message, &retry_at],
     )
     .await
-    .context("Updating job error message")?;
+    .context("Recording job failure")?;
 
     Ok(())
 }
@@ -96,28 +285,214 @@ pub async fn get_job_by_name_and_scheduled_at(
     deserialize_job(&job)
 }
 
-// Selects all jobs with:
+// Claims all jobs with:
 //  - scheduled_at in the past
-//  - error_message is null or executed_at is at least 60 minutes ago (intended to make repeat executions rare enough)
-pub async fn get_jobs_to_execute(db: &DbClient) -> Result<Vec<Job>> {
+//  - not failed for good
+//  - retry_at, if the job failed before, in the past
+//  - not claimed by another instance, or whose claim has expired
+//
+// At most `limit` jobs are claimed, by priority and then earliest first. Claimed jobs are locked for
+// `lock_duration`, so that several instances sharing the database don't run
+// the same job twice.
+pub async fn claim_jobs_to_execute(
+    db: &DbClient,
+    lock_duration: std::time::Duration,
+    limit: i64,
+) -> Result<Vec<Job>> {
+    let lock_secs = lock_duration.as_secs_f64();
     let jobs = db
         .query(
             "
-        SELECT * FROM jobs WHERE scheduled_at <= now() AND (error_message IS NULL OR executed_at <= now() - INTERVAL '60 minutes')",
-            &[],
+        UPDATE jobs SET locked_until = now() + $1 * INTERVAL '1 second'
+        WHERE id IN (
+            SELECT id FROM jobs
+            WHERE scheduled_at <= now() AND (retry_at IS NULL OR retry_at <= now())
+                AND (locked_until IS NULL OR locked_until <= now())
+            ORDER BY priority DESC, scheduled_at
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING *",
+            &[&lock_secs, &limit],
         )
         .await
-        .context("Getting jobs data")?;
+        .context("Claiming jobs to execute")?;
 
     let mut data = Vec::with_capacity(jobs.len());
     for job in jobs {
         let serialized_job = deserialize_job(&job);
         data.push(serialized_job.unwrap());
     }
+    data.sort_by_key(|job| (std::cmp::Reverse(job.priority), job.scheduled_at));
 
     Ok(data)
 }
 
+pub async fn insert_job_schedule(
+    db: &DbClient,
+    name: &str,
+    cron: Option<&str>,
+    interval_secs: Option<i64>,
+    metadata: &serde_json::Value,
+) -> Result<Uuid> {
+    tracing::trace!("insert_job_schedule(name={})", name);
+
+    let row = db
+        .query_one(
+            "INSERT INTO job_schedules (name, cron, interval_secs, metadata) VALUES ($1, $2, $3, $4)
+                RETURNING id",
+            &[&name, &cron, &interval_secs, &metadata],
+        )
+        .await
+        .context("Inserting job schedule")?;
+
+    Ok(row.try_get(0)?)
+}
+
+pub async fn delete_job_schedule(db: &DbClient, id: &Uuid) -> Result<()> {
+    tracing::trace!("delete_job_schedule(id={})", id);
+
+    db.execute("DELETE FROM job_schedules WHERE id = $1", &[&id])
+        .await
+        .context("Deleting job schedule")?;
+
+    Ok(())
+}
+
+pub async fn get_job_schedules(db: &DbClient) -> Result<Vec<StoredJobSchedule>> {
+    let rows = db
+        .query(
+            "SELECT id, name, cron, interval_secs, metadata FROM job_schedules",
+            &[],
+        )
+        .await
+        .context("Getting job schedules")?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(StoredJobSchedule {
+                id: row.try_get(0)?,
+                name: row.try_get(1)?,
+                cron: row.try_get(2)?,
+                interval_secs: row.try_get(3)?,
+                metadata: row.try_get(4)?,
+            })
+        })
+        .collect()
+}
+
+/// Selects all jobs which are still going to run, earliest first.
+pub async fn get_pending_jobs(db: &DbClient) -> Result<Vec<Job>> {
+    let jobs = db
+        .query("SELECT * FROM jobs ORDER BY scheduled_at", &[])
+        .await
+        .context("Getting pending jobs")?;
+
+    jobs.iter().map(deserialize_job).collect()
+}
+
+/// Releases the claims on jobs which won't be run by this instance after all.
+pub async fn release_jobs(db: &DbClient, ids: &[Uuid]) -> Result<()> {
+    tracing::trace!("release_jobs(ids={:?})", ids);
+
+    db.execute(
+        "UPDATE jobs SET locked_until = NULL WHERE id = ANY($1)",
+        &[&ids],
+    )
+    .await
+    .context("Releasing jobs")?;
+
+    Ok(())
+}
+
+/// Moves a job which failed for good from `jobs` into `jobs_dead_letter`.
+pub async fn move_job_to_dead_letter(db: &DbClient, id: &Uuid) -> Result<()> {
+    tracing::trace!("move_job_to_dead_letter(id={})", id);
+
+    db.execute(
+        "WITH dead AS (DELETE FROM jobs WHERE id = $1 RETURNING *)
+        INSERT INTO jobs_dead_letter
+            (id, name, scheduled_at, metadata, attempts, error_message, failed_at)
+            SELECT id, name, scheduled_at, metadata, attempts, error_message,
+                coalesce(failed_at, now())
+            FROM dead",
+        &[&id],
+    )
+    .await
+    .context("Moving job to dead letter queue")?;
+
+    Ok(())
+}
+
+/// A job which failed too many times to be retried.
+#[derive(Serialize, Debug)]
+pub struct DeadJob {
+    pub id: Uuid,
+    pub name: String,
+    pub scheduled_at: DateTime<Utc>,
+    pub metadata: serde_json::Value,
+    pub attempts: i32,
+    pub error_message: Option<String>,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// Selects the jobs in the dead letter queue, most recently failed first.
+pub async fn get_dead_letter_jobs(db: &DbClient) -> Result<Vec<DeadJob>> {
+    let rows = db
+        .query(
+            "SELECT id, name, scheduled_at, metadata, attempts, error_message, failed_at
+            FROM jobs_dead_letter ORDER BY failed_at DESC",
+            &[],
+        )
+        .await
+        .context("Getting dead letter jobs")?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(DeadJob {
+                id: row.try_get(0)?,
+                name: row.try_get(1)?,
+                scheduled_at: row.try_get(2)?,
+                metadata: row.try_get(3)?,
+                attempts: row.try_get(4)?,
+                error_message: row.try_get(5)?,
+                failed_at: row.try_get(6)?,
+            })
+        })
+        .collect()
+}
+
+/// Counts the jobs in the queue per job name.
+pub async fn get_queue_depth(db: &DbClient) -> Result<Vec<crate::jobs::metrics::QueueDepth>> {
+    let rows = db
+        .query(
+            "SELECT name,
+                count(*) FILTER (WHERE NOT dead),
+                count(*) FILTER (WHERE NOT dead AND scheduled_at <= now()),
+                count(*) FILTER (WHERE dead)
+            FROM (
+                SELECT name, scheduled_at, false AS dead FROM jobs
+                UNION ALL
+                SELECT name, scheduled_at, true FROM jobs_dead_letter
+            ) AS all_jobs
+            GROUP BY name ORDER BY name",
+            &[],
+        )
+        .await
+        .context("Getting job queue depth")?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(crate::jobs::metrics::QueueDepth {
+                name: row.try_get(0)?,
+                pending: row.try_get(1)?,
+                overdue: row.try_get(2)?,
+                failed: row.try_get(3)?,
+            })
+        })
+        .collect()
+}
+
 fn deserialize_job(row: &tokio_postgres::row::Row) -> Result<Job> {
     let id: Uuid = row.try_get(0)?;
     let name: String = row.try_get(1)?;
@@ -125,6 +500,11 @@ fn deserialize_job(row: &tokio_postgres::row::Row) -> Result<Job> {
     let metadata: serde_json::Value = row.try_get(3)?;
     let executed_at: Option<DateTime<Utc>> = row.try_get(4)?;
     let error_message: Option<String> = row.try_get(5)?;
+    let attempts: i32 = row.try_get(6)?;
+    let retry_at: Option<DateTime<Utc>> = row.try_get(7)?;
+    let failed_at: Option<DateTime<Utc>> = row.try_get(8)?;
+    // 9 is `locked_until`, which is only used in queries.
+    let priority: i16 = row.try_get(10)?;
 
     Ok(Job {
         id,
@@ -133,5 +513,64 @@ fn deserialize_job(row: &tokio_postgres::row::Row) -> Result<Job> {
         metadata,
         executed_at,
         error_message,
+        attempts,
+        retry_at,
+        failed_at,
+        priority,
     })
 }
+
+#[test]
+fn stored_schedule_next_run() {
+    let mut schedule = StoredJobSchedule {
+        id: Uuid::nil(),
+        name: "test".to_string(),
+        cron: None,
+        interval_secs: Some(3600),
+        metadata: serde_json::Value::Null,
+    };
+    let now = Utc.ymd(2023, 1, 2).and_hms(10, 20, 0);
+    assert_eq!(
+        schedule.next_run(now).unwrap(),
+        Some(Utc.ymd(2023, 1, 2).and_hms(11, 0, 0))
+    );
+
+    schedule.interval_secs = None;
+    schedule.cron = Some("0 30 9 * * * *".to_string());
+    assert_eq!(
+        schedule.next_run(now).unwrap(),
+        Some(Utc.ymd(2023, 1, 3).and_hms(9, 30, 0))
+    );
+
+    schedule.interval_secs = Some(60);
+    assert!(schedule.next_run(now).is_err());
+}
+
+#[test]
+fn stored_schedule_runs_until() {
+    let mut schedule = StoredJobSchedule {
+        id: Uuid::nil(),
+        name: "test".to_string(),
+        cron: None,
+        interval_secs: Some(600),
+        metadata: serde_json::Value::Null,
+    };
+    let now = Utc.ymd(2023, 1, 2).and_hms(10, 25, 0);
+    let until = Utc.ymd(2023, 1, 2).and_hms(11, 0, 0);
+    assert_eq!(
+        schedule.runs_until(now, until).unwrap(),
+        [
+            Utc.ymd(2023, 1, 2).and_hms(10, 30, 0),
+            Utc.ymd(2023, 1, 2).and_hms(10, 40, 0),
+            Utc.ymd(2023, 1, 2).and_hms(10, 50, 0),
+            Utc.ymd(2023, 1, 2).and_hms(11, 0, 0),
+        ]
+    );
+
+    schedule.interval_secs = None;
+    schedule.cron = Some("0 30 9 * * * *".to_string());
+    assert_eq!(
+        schedule.runs_until(now, until).unwrap(),
+        [Utc.ymd(2023, 1, 3).and_hms(9, 30, 0)]
+    );
+}
@@ -0,0 +1,81 @@
+//! The `jobs_history` table records every execution of a scheduled job, so
+//! that failures can be investigated after the job itself is gone.
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio_postgres::Client as DbClient;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct JobExecution {
+    pub job_id: Uuid,
+    pub name: String,
+    pub metadata: serde_json::Value,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: i64,
+    pub succeeded: bool,
+    pub error_message: Option<String>,
+    /// What a successful job returned, if anything.
+    pub output: Option<serde_json::Value>,
+}
+
+pub async fn record_job_execution(db: &DbClient, execution: &JobExecution) -> Result<()> {
+    tracing::trace!("record_job_execution(job_id={})", execution.job_id);
+
+    db.execute(
+        "INSERT INTO jobs_history
+            (job_id, name, metadata, started_at, duration_ms, succeeded, error_message, output)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        &[
+            &execution.job_id,
+            &execution.name,
+            &execution.metadata,
+            &execution.started_at,
+            &execution.duration_ms,
+            &execution.succeeded,
+            &execution.error_message,
+            &execution.output,
+        ],
+    )
+    .await
+    .context("Recording job execution")?;
+
+    Ok(())
+}
+
+/// Returns the most recent executions, newest first, optionally only of the
+/// jobs named `name` or only the failed ones.
+pub async fn get_job_executions(
+    db: &DbClient,
+    name: Option<&str>,
+    failed_only: bool,
+    limit: i64,
+) -> Result<Vec<JobExecution>> {
+    let rows = db
+        .query(
+            "SELECT job_id, name, metadata, started_at, duration_ms, succeeded, error_message,
+                    output
+                FROM jobs_history
+                WHERE ($1::TEXT IS NULL OR name = $1) AND (NOT $2 OR NOT succeeded)
+                ORDER BY started_at DESC
+                LIMIT $3",
+            &[&name, &failed_only, &limit],
+        )
+        .await
+        .context("Getting job executions")?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(JobExecution {
+                job_id: row.try_get(0)?,
+                name: row.try_get(1)?,
+                metadata: row.try_get(2)?,
+                started_at: row.try_get(3)?,
+                duration_ms: row.try_get(4)?,
+                succeeded: row.try_get(5)?,
+                error_message: row.try_get(6)?,
+                output: row.try_get(7)?,
+            })
+        })
+        .collect()
+}
@@ -6,11 +6,12 @@ use futures::{future::BoxFuture, FutureExt};
 use hyper::header::HeaderValue;
 use once_cell::sync::OnceCell;
 use regex::Regex;
-use reqwest::header::{AUTHORIZATION, USER_AGENT};
-use reqwest::{Client, Request, RequestBuilder, Response, StatusCode};
+use reqwest::header::{HeaderMap, AUTHORIZATION, USER_AGENT};
+use reqwest::{Client, Request, RequestBuilder, StatusCode};
 use std::collections::{HashMap, HashSet};
 use std::{
     fmt,
+    sync::Arc,
     time::{Duration, SystemTime},
 };
 use tracing as log;
@@ -21,53 +22,103 @@ pub struct User {
     pub id: Option<u64>,
 }
 
+/// A response which has already been read in full.
+struct ReadResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    error: Option<reqwest::Error>,
+    body: Bytes,
+}
+
 impl GithubClient {
     async fn send_req(&self, req: RequestBuilder) -> anyhow::Result<(Bytes, String)> {
         const MAX_ATTEMPTS: u32 = 2;
         log::debug!("send_req with {:?}", req);
         let req_dbg = format!("{:?}", req);
-        let req = req
+        let mut req = req
             .build()
             .with_context(|| format!("building reqwest {}", req_dbg))?;
+        self.authorize(&mut req).await?;
 
-        let mut resp = self.client.execute(req.try_clone().unwrap()).await?;
-        if let Some(sleep) = Self::needs_retry(&resp).await {
-            resp = self.retry(req, sleep, MAX_ATTEMPTS).await?;
+        let mut resp = self.execute(req.try_clone().unwrap(), &req_dbg).await?;
+        if let Some(sleep) = Self::needs_retry(&resp) {
+            resp = self.retry(req, &req_dbg, sleep, MAX_ATTEMPTS).await?;
+        }
+        let rate_limited = Self::needs_retry(&resp).is_some();
+        if let Some(e) = resp.error {
+            let err = Err(anyhow::Error::new(e))
+                .with_context(|| format!("response: {}", String::from_utf8_lossy(&resp.body)));
+            return if rate_limited {
+                err.context("GitHub rate limit exceeded, even after retrying")
+            } else {
+                err
+            };
         }
-        let maybe_err = resp.error_for_status_ref().err();
+
+        Ok((resp.body, req_dbg))
+    }
+
+    /// Sends `req` and reads the whole response, since the body is needed to
+    /// tell secondary rate limits apart from other 403s.
+    async fn execute(&self, req: Request, req_dbg: &str) -> anyhow::Result<ReadResponse> {
+        let resp = self.client.execute(req).await?;
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let error = resp.error_for_status_ref().err();
         let body = resp
             .bytes()
             .await
             .with_context(|| format!("failed to read response body {req_dbg}"))?;
-        if let Some(e) = maybe_err {
-            return Err(anyhow::Error::new(e))
-                .with_context(|| format!("response: {}", String::from_utf8_lossy(&body)));
-        }
-
-        Ok((body, req_dbg))
+        Ok(ReadResponse {
+            status,
+            headers,
+            error,
+            body,
+        })
     }
 
-    async fn needs_retry(resp: &Response) -> Option<Duration> {
+    /// Returns how long to wait before retrying a request which was rejected
+    /// by one of GitHub's rate limits, or `None` if it wasn't.
+    fn needs_retry(resp: &ReadResponse) -> Option<Duration> {
         const REMAINING: &str = "X-RateLimit-Remaining";
         const RESET: &str = "X-RateLimit-Reset";
+        const RETRY_AFTER: &str = "Retry-After";
+        /// How long to wait for a secondary rate limit which doesn't say.
+        const SECONDARY_RATE_LIMIT_BACKOFF_IN_SECS: u64 = 60;
 
-        if resp.status().is_success() {
+        let status = resp.status;
+        if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
             return None;
         }
 
-        let headers = resp.headers();
-        if !(headers.contains_key(REMAINING) && headers.contains_key(RESET)) {
-            return None;
+        let headers = &resp.headers;
+        // Secondary rate limits, e.g. for creating too much content at once,
+        // say how long to wait.
+        if let Some(retry_after) = headers
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+        {
+            return Some(Duration::from_secs(retry_after));
         }
 
-        // Weird github api behavior. It asks us to retry but also has a remaining count above 1
-        // Try again immediately and hope for the best...
-        if headers[REMAINING] != "0" {
-            return Some(Duration::from_secs(0));
+        if headers.contains_key(RESET) && matches!(headers.get(REMAINING), Some(v) if v == "0") {
+            let reset_time = headers[RESET].to_str().ok()?.parse::<u64>().ok()?;
+            return Some(Duration::from_secs(Self::calc_sleep(reset_time) + 10));
         }
 
-        let reset_time = headers[RESET].to_str().unwrap().parse::<u64>().unwrap();
-        Some(Duration::from_secs(Self::calc_sleep(reset_time) + 10))
+        // Other 403s still carry the primary rate limit headers, so only the
+        // message tells a secondary rate limit apart from e.g. a missing
+        // permission.
+        let secondary = status == StatusCode::TOO_MANY_REQUESTS
+            || String::from_utf8_lossy(&resp.body)
+                .to_lowercase()
+                .contains("secondary rate limit");
+        if secondary {
+            Some(Duration::from_secs(SECONDARY_RATE_LIMIT_BACKOFF_IN_SECS))
+        } else {
+            None
+        }
     }
 
     fn calc_sleep(reset_time: u64) -> u64 {
@@ -75,12 +126,13 @@ impl GithubClient {
         reset_time.saturating_sub(epoch_time)
     }
 
-    fn retry(
-        &self,
-        req: Request,
+    fn retry<'a>(
+        &'a self,
+        mut req: Request,
+        req_dbg: &'a str,
         sleep: Duration,
         remaining_attempts: u32,
-    ) -> BoxFuture<Result<Response, reqwest::Error>> {
+    ) -> BoxFuture<'a, anyhow::Result<ReadResponse>> {
         #[derive(Debug, serde::Deserialize)]
         struct RateLimit {
             #[allow(unused)]
@@ -114,16 +166,14 @@ impl GithubClient {
             tokio::time::sleep(sleep).await;
 
             // check rate limit
-            let rate_resp = self
+            let mut rate_req = self
                 .client
-                .execute(
-                    self.client
-                        .get(&format!("{}/rate_limit", self.api_url))
-                        .configure(self)
-                        .build()
-                        .unwrap(),
-                )
-                .await?;
+                .get(&format!("{}/rate_limit", self.api_url))
+                .configure(self)
+                .build()
+                .unwrap();
+            self.authorize(&mut rate_req).await?;
+            let rate_resp = self.client.execute(rate_req).await?;
             rate_resp.error_for_status_ref()?;
             let rate_limit_response = rate_resp.json::<RateLimitResponse>().await?;
 
@@ -148,10 +198,16 @@ impl GithubClient {
                 }
             }
 
-            let resp = self.client.execute(req.try_clone().unwrap()).await?;
-            if let Some(sleep) = Self::needs_retry(&resp).await {
+            // An installation token may have expired while we were waiting.
+            self.authorize(&mut req).await?;
+            let resp = self.execute(req.try_clone().unwrap(), req_dbg).await?;
+            if let Some(next_sleep) = Self::needs_retry(&resp) {
                 if remaining_attempts > 0 {
-                    return self.retry(req, sleep, remaining_attempts - 1).await;
+                    // Back off further every time we are still limited.
+                    let next_sleep = next_sleep.max(sleep * 2);
+                    return self
+                        .retry(req, req_dbg, next_sleep, remaining_attempts - 1)
+                        .await;
                 }
             }
 
@@ -1962,14 +2018,157 @@ trait RequestSend: Sized {
 }
 
 impl RequestSend for RequestBuilder {
+    /// Installation tokens of a GitHub App may need to be refreshed first, so
+    /// they are added by `GithubClient::authorize` once the request is built.
     fn configure(self, g: &GithubClient) -> RequestBuilder {
-        let mut auth = HeaderValue::from_maybe_shared(format!("token {}", g.token)).unwrap();
+        let builder = self.header(USER_AGENT, "rust-lang-triagebot");
+        match &g.auth {
+            GithubAuth::Token(token) => {
+                let mut auth = HeaderValue::from_maybe_shared(format!("token {}", token)).unwrap();
+                auth.set_sensitive(true);
+                builder.header(AUTHORIZATION, &auth)
+            }
+            GithubAuth::App { .. } => builder,
+        }
+    }
+}
+
+/// How long before it expires an installation token is replaced.
+const INSTALLATION_TOKEN_REFRESH_MARGIN_IN_SECS: i64 = 5 * 60;
+
+/// A GitHub App, which the bot can authenticate as instead of using a
+/// personal access token, with the permissions granted to its installations.
+pub struct GithubApp {
+    pub app_id: u64,
+    /// The installation used by `GithubClient::new_from_env`.
+    pub installation_id: u64,
+    key: jsonwebtoken::EncodingKey,
+    /// The current token of every installation used so far.
+    installation_tokens: tokio::sync::Mutex<HashMap<u64, InstallationToken>>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+struct InstallationToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl InstallationToken {
+    fn needs_refresh(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at - now < chrono::Duration::seconds(INSTALLATION_TOKEN_REFRESH_MARGIN_IN_SECS)
+    }
+}
+
+impl GithubApp {
+    pub fn new(app_id: u64, installation_id: u64, private_key: &[u8]) -> anyhow::Result<Self> {
+        Ok(GithubApp {
+            app_id,
+            installation_id,
+            key: jsonwebtoken::EncodingKey::from_rsa_pem(private_key)
+                .context("invalid GitHub App private key")?,
+            installation_tokens: tokio::sync::Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Reads the app from `GITHUB_APP_ID`, `GITHUB_APP_INSTALLATION_ID` and
+    /// `GITHUB_APP_PRIVATE_KEY` (in PEM format), or returns `None` if
+    /// `GITHUB_APP_ID` isn't set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let app_id = match std::env::var("GITHUB_APP_ID") {
+            Ok(id) => id.parse().context("invalid GITHUB_APP_ID")?,
+            Err(_) => return Ok(None),
+        };
+        let installation_id = std::env::var("GITHUB_APP_INSTALLATION_ID")
+            .context("GITHUB_APP_INSTALLATION_ID must be set with GITHUB_APP_ID")?
+            .parse()
+            .context("invalid GITHUB_APP_INSTALLATION_ID")?;
+        let private_key = std::env::var("GITHUB_APP_PRIVATE_KEY")
+            .context("GITHUB_APP_PRIVATE_KEY must be set with GITHUB_APP_ID")?;
+        Ok(Some(Self::new(
+            app_id,
+            installation_id,
+            private_key.as_bytes(),
+        )?))
+    }
+
+    pub fn key(&self) -> &jsonwebtoken::EncodingKey {
+        &self.key
+    }
+
+    /// Creates a short-lived JWT authenticating as the app itself.
+    fn jwt(&self) -> anyhow::Result<String> {
+        #[derive(serde::Serialize)]
+        struct Claims {
+            iat: i64,
+            exp: i64,
+            iss: String,
+        }
+
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            // Allow for the clocks being a bit out of sync.
+            iat: now - 60,
+            exp: now + 9 * 60,
+            iss: self.app_id.to_string(),
+        };
+        jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
+            &claims,
+            &self.key,
+        )
+        .context("failed to sign GitHub App JWT")
+    }
+
+    /// Returns a token for `installation_id`, exchanging a JWT for a new one
+    /// if there is no cached token or it is about to expire.
+    async fn installation_token(
+        &self,
+        client: &Client,
+        api_url: &str,
+        installation_id: u64,
+    ) -> anyhow::Result<String> {
+        let mut tokens = self.installation_tokens.lock().await;
+        if let Some(token) = tokens.get(&installation_id) {
+            if !token.needs_refresh(Utc::now()) {
+                return Ok(token.token.clone());
+            }
+        }
+
+        log::debug!("refreshing token of installation {}", installation_id);
+        let mut auth = HeaderValue::from_maybe_shared(format!("Bearer {}", self.jwt()?)).unwrap();
         auth.set_sensitive(true);
-        self.header(USER_AGENT, "rust-lang-triagebot")
-            .header(AUTHORIZATION, &auth)
+        let resp = client
+            .post(&format!(
+                "{}/app/installations/{}/access_tokens",
+                api_url, installation_id
+            ))
+            .header(USER_AGENT, "rust-lang-triagebot")
+            .header(AUTHORIZATION, auth)
+            .send()
+            .await
+            .context("failed to request installation token")?;
+        let token: InstallationToken = resp
+            .error_for_status()
+            .context("failed to request installation token")?
+            .json()
+            .await
+            .context("failed to read installation token")?;
+        tokens.insert(installation_id, token.clone());
+        Ok(token.token)
     }
 }
 
+#[derive(Clone)]
+enum GithubAuth {
+    /// A personal access token.
+    Token(String),
+    /// An installation of a GitHub App.
+    App {
+        app: Arc<GithubApp>,
+        installation_id: u64,
+    },
+}
+
 /// Finds the token in the user's environment, panicking if no suitable token
 /// can be found.
 pub fn default_token_from_env() -> String {
@@ -2001,7 +2200,7 @@ fn get_token_from_git_config() -> anyhow::Result<String> {
 
 #[derive(Clone)]
 pub struct GithubClient {
-    token: String,
+    auth: GithubAuth,
     client: Client,
     api_url: String,
     graphql_url: String,
@@ -2012,23 +2211,99 @@ impl GithubClient {
     pub fn new(token: String, api_url: String, graphql_url: String, raw_url: String) -> Self {
         GithubClient {
             client: Client::new(),
-            token,
+            auth: GithubAuth::Token(token),
             api_url,
             graphql_url,
             raw_url,
         }
     }
 
+    /// Creates a client acting as the default installation of `app`.
+    pub fn new_with_app(
+        app: Arc<GithubApp>,
+        api_url: String,
+        graphql_url: String,
+        raw_url: String,
+    ) -> Self {
+        GithubClient {
+            client: Client::new(),
+            auth: GithubAuth::App {
+                installation_id: app.installation_id,
+                app,
+            },
+            api_url,
+            graphql_url,
+            raw_url,
+        }
+    }
+
+    /// Authenticates as the GitHub App configured in the environment if there
+    /// is one (see `GithubApp::from_env`), or with `default_token_from_env`.
     pub fn new_from_env() -> Self {
-        Self::new(
-            default_token_from_env(),
-            std::env::var("GITHUB_API_URL")
-                .unwrap_or_else(|_| "https://api.github.com".to_string()),
-            std::env::var("GITHUB_GRAPHQL_API_URL")
-                .unwrap_or_else(|_| "https://api.github.com/graphql".to_string()),
-            std::env::var("GITHUB_RAW_URL")
-                .unwrap_or_else(|_| "https://raw.githubusercontent.com".to_string()),
-        )
+        let api_url = std::env::var("GITHUB_API_URL")
+            .unwrap_or_else(|_| "https://api.github.com".to_string());
+        let graphql_url = std::env::var("GITHUB_GRAPHQL_API_URL")
+            .unwrap_or_else(|_| "https://api.github.com/graphql".to_string());
+        let raw_url = std::env::var("GITHUB_RAW_URL")
+            .unwrap_or_else(|_| "https://raw.githubusercontent.com".to_string());
+        match GithubApp::from_env().expect("invalid GitHub App configuration") {
+            Some(app) => Self::new_with_app(Arc::new(app), api_url, graphql_url, raw_url),
+            None => Self::new(default_token_from_env(), api_url, graphql_url, raw_url),
+        }
+    }
+
+    /// The GitHub App this client authenticates as, if any.
+    pub fn app(&self) -> Option<&GithubApp> {
+        match &self.auth {
+            GithubAuth::Token(_) => None,
+            GithubAuth::App { app, .. } => Some(app.as_ref()),
+        }
+    }
+
+    /// Returns a client acting as another installation of the same GitHub
+    /// App, e.g. one in a different organization. Tokens are cached across
+    /// all the clients of an app.
+    pub fn for_installation(&self, installation_id: u64) -> anyhow::Result<Self> {
+        let app = match &self.auth {
+            GithubAuth::Token(_) => anyhow::bail!("not authenticated as a GitHub App"),
+            GithubAuth::App { app, .. } => app.clone(),
+        };
+        Ok(GithubClient {
+            auth: GithubAuth::App {
+                app,
+                installation_id,
+            },
+            ..self.clone()
+        })
+    }
+
+    /// Adds the installation token to a request built by a GitHub App client,
+    /// unless it goes somewhere other than GitHub, e.g. to the team API
+    /// through `raw()`.
+    async fn authorize(&self, req: &mut Request) -> anyhow::Result<()> {
+        if let GithubAuth::App {
+            app,
+            installation_id,
+        } = &self.auth
+        {
+            if !self.is_github_url(req.url()) {
+                return Ok(());
+            }
+            let token = app
+                .installation_token(&self.client, &self.api_url, *installation_id)
+                .await?;
+            let mut auth = HeaderValue::from_maybe_shared(format!("token {}", token)).unwrap();
+            auth.set_sensitive(true);
+            req.headers_mut().insert(AUTHORIZATION, auth);
+        }
+        Ok(())
+    }
+
+    fn is_github_url(&self, url: &url::Url) -> bool {
+        let origin = url.origin();
+        [&self.api_url, &self.graphql_url, &self.raw_url]
+            .iter()
+            .any(|base| matches!(url::Url::parse(base), Ok(base) if base.origin() == origin))
     }
 
     pub fn raw(&self) -> &Client {
@@ -2044,9 +2319,10 @@ impl GithubClient {
         let url = format!("{}/{repo}/{branch}/{path}", self.raw_url);
         let req = self.get(&url);
         let req_dbg = format!("{:?}", req);
-        let req = req
+        let mut req = req
             .build()
             .with_context(|| format!("failed to build request {:?}", req_dbg))?;
+        self.authorize(&mut req).await?;
         let resp = self.client.execute(req).await.context(req_dbg.clone())?;
         let status = resp.status();
         let body = resp
@@ -2780,6 +3056,128 @@ impl Submodule {
 mod tests {
     use super::*;
 
+    #[test]
+    fn rate_limit_retries() {
+        let resp = |status: u16, headers: &[(&str, &str)], body: &'static str| {
+            let mut header_map = HeaderMap::new();
+            for (name, value) in headers {
+                header_map.insert(
+                    reqwest::header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                    HeaderValue::from_str(value).unwrap(),
+                );
+            }
+            ReadResponse {
+                status: StatusCode::from_u16(status).unwrap(),
+                headers: header_map,
+                error: None,
+                body: Bytes::from(body),
+            }
+        };
+        let in_an_hour = (SystemTime::UNIX_EPOCH.elapsed().unwrap().as_secs() + 3600).to_string();
+
+        assert_eq!(GithubClient::needs_retry(&resp(200, &[], "")), None);
+        assert_eq!(GithubClient::needs_retry(&resp(404, &[], "")), None);
+        assert_eq!(
+            GithubClient::needs_retry(&resp(
+                404,
+                &[("X-RateLimit-Remaining", "10"), ("X-RateLimit-Reset", "0")],
+                ""
+            )),
+            None
+        );
+        assert_eq!(
+            GithubClient::needs_retry(&resp(403, &[("Retry-After", "30")], "")),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            GithubClient::needs_retry(&resp(429, &[], "")),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(GithubClient::needs_retry(&resp(403, &[], "")), None);
+        let sleep = GithubClient::needs_retry(&resp(
+            403,
+            &[
+                ("X-RateLimit-Remaining", "0"),
+                ("X-RateLimit-Reset", &in_an_hour),
+            ],
+            "",
+        ))
+        .unwrap();
+        assert!(sleep > Duration::from_secs(3500) && sleep <= Duration::from_secs(3610));
+
+        // Secondary rate limits leave the primary limit untouched.
+        let remaining = [
+            ("X-RateLimit-Remaining", "4990"),
+            ("X-RateLimit-Reset", in_an_hour.as_str()),
+        ];
+        assert_eq!(
+            GithubClient::needs_retry(&resp(
+                403,
+                &remaining,
+                r#"{"message": "You have exceeded a secondary rate limit. Please wait a few minutes before you try again."}"#
+            )),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            GithubClient::needs_retry(&resp(
+                403,
+                &remaining,
+                r#"{"message": "Resource not accessible by integration"}"#
+            )),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn installation_token_only_sent_to_github() {
+        let app = GithubApp {
+            app_id: 1,
+            installation_id: 2,
+            key: jsonwebtoken::EncodingKey::from_secret(b"unused"),
+            installation_tokens: tokio::sync::Mutex::new(HashMap::from([(
+                2,
+                InstallationToken {
+                    token: "secret".to_string(),
+                    expires_at: Utc::now() + chrono::Duration::hours(1),
+                },
+            )])),
+        };
+        let client = GithubClient::new_with_app(
+            Arc::new(app),
+            "https://api.github.com".to_string(),
+            "https://api.github.com/graphql".to_string(),
+            "https://raw.githubusercontent.com".to_string(),
+        );
+
+        let mut req = client
+            .get("https://api.github.com/repos/rust-lang/rust")
+            .build()
+            .unwrap();
+        client.authorize(&mut req).await.unwrap();
+        assert_eq!(req.headers()[AUTHORIZATION], "token secret");
+
+        let mut req = client
+            .raw()
+            .get("https://team-api.infra.rust-lang.org/v1/teams.json")
+            .build()
+            .unwrap();
+        client.authorize(&mut req).await.unwrap();
+        assert!(!req.headers().contains_key(AUTHORIZATION));
+    }
+
+    #[test]
+    fn installation_token_refresh() {
+        let now = Utc::now();
+        let token = |expires_in_mins| InstallationToken {
+            token: "token".to_string(),
+            expires_at: now + chrono::Duration::minutes(expires_in_mins),
+        };
+        assert!(!token(60).needs_refresh(now));
+        assert!(!token(6).needs_refresh(now));
+        assert!(token(4).needs_refresh(now));
+        assert!(token(-1).needs_refresh(now));
+    }
+
     #[test]
     fn display_labels() {
         let x = UnknownLabels {
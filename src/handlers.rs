@@ -1,7 +1,8 @@
 use crate::config::{self, Config, ConfigurationError};
 use crate::github::{Event, GithubClient, IssueCommentAction, IssuesAction, IssuesEvent};
 use octocrab::Octocrab;
-use parser::command::{assign::AssignCommand, Command, Input};
+use parser::command::{assign::AssignCommand, expand_aliases, Command, Input};
+use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
 use tracing as log;
@@ -47,6 +48,7 @@ mod rfc_helper;
 pub mod rustc_commits;
 mod shortcut;
 pub mod types_planning_updates;
+mod unknown_command;
 mod validate_config;
 
 pub async fn handle(ctx: &Context, event: &Event) -> Vec<HandlerError> {
@@ -207,9 +209,18 @@ macro_rules! command_handlers {
                 }
             }
 
-            let input = Input::new(&body, vec![&ctx.username, "triagebot"]);
-            let commands = if let Some(previous) = event.comment_from() {
-                let prev_commands = Input::new(&previous, vec![&ctx.username, "triagebot"]).collect::<Vec<_>>();
+            let bots = ctx.trigger_names();
+            let no_aliases = HashMap::new();
+            let aliases = config
+                .as_ref()
+                .ok()
+                .and_then(|c| c.command_aliases.as_ref())
+                .map_or(&no_aliases, |c| &c.aliases);
+            let body = expand_aliases(body, &bots, aliases);
+            let input = Input::new(&body, bots.clone());
+            let previous = event.comment_from().map(|previous| expand_aliases(previous, &bots, aliases));
+            let commands = if let Some(previous) = &previous {
+                let prev_commands = Input::new(&previous, bots.clone()).collect::<Vec<_>>();
                 input.filter(|cmd| !prev_commands.contains(cmd)).collect::<Vec<_>>()
             } else {
                 input.collect()
@@ -226,10 +237,11 @@ macro_rules! command_handlers {
                 Err(e @ ConfigurationError::Missing) => {
                     // r? is conventionally used to mean "hey, can you review"
                     // even if the repo doesn't have a triagebot.toml. In that
-                    // case, just ignore it.
+                    // case, just ignore it. Likewise don't complain about
+                    // words that merely look like commands.
                     if commands
                         .iter()
-                        .all(|cmd| matches!(cmd, Command::Assign(Ok(AssignCommand::ReviewName { .. }))))
+                        .all(|cmd| matches!(cmd, Command::Assign(Ok(AssignCommand::ReviewName { .. })) | Command::Unknown(_)))
                     {
                         return;
                     }
@@ -261,19 +273,50 @@ macro_rules! command_handlers {
                         }
                     }
                     Command::$enum(Err(err)) => {
-                        errors.push(HandlerError::Message(format!(
-                            "Parsing {} command in [comment]({}) failed: {}",
+                        errors.push(HandlerError::Message(parse_error_message(
                             stringify!($name),
                             event.html_url().expect("has html url"),
-                            err
+                            &err,
                         )));
                     })*
+                    Command::Unknown(Ok(command)) => {
+                        // Mentions of the bot in prose also end up here, so
+                        // only answer them where this was asked for.
+                        if let Some(config) = &config.unknown_command {
+                            unknown_command::handle_command(ctx, config, event, command)
+                                .await
+                                .unwrap_or_else(|err| errors.push(HandlerError::Other(err)));
+                        }
+                    }
+                    Command::Unknown(Err(err)) => {
+                        if config.unknown_command.is_some() {
+                            errors.push(HandlerError::Message(parse_error_message(
+                                "unknown",
+                                event.html_url().expect("has html url"),
+                                &err,
+                            )));
+                        }
+                    }
                 }
             }
         }
     }
 }
 
+/// Describes a command that failed to parse, quoting the offending part of
+/// the comment when there is one.
+fn parse_error_message(name: &str, url: &str, err: &parser::error::Error<'_>) -> String {
+    let fragment = err.fragment();
+    if fragment.is_empty() {
+        format!("Parsing {name} command in [comment]({url}) failed: {err}")
+    } else {
+        format!(
+            "Parsing {name} command in [comment]({url}) failed at `{fragment}`: {}",
+            err.source
+        )
+    }
+}
+
 // Handle commands in comments/issues body
 //
 // This is for handlers for commands parsed by the `parser` crate.
@@ -296,5 +339,24 @@ pub struct Context {
     pub github: GithubClient,
     pub db: crate::db::ClientPool,
     pub username: String,
+    /// The names which, when mentioned, trigger commands (e.g. `rustbot`).
+    pub trigger_names: Vec<String>,
     pub octocrab: Octocrab,
 }
+
+impl Context {
+    pub fn trigger_names(&self) -> Vec<&str> {
+        self.trigger_names.iter().map(String::as_str).collect()
+    }
+
+    /// Schedules the job `name` to run at `when`. If a job with the same name
+    /// and metadata is already pending, it is moved to `when` instead.
+    pub async fn schedule_job(
+        &self,
+        name: &str,
+        when: chrono::DateTime<chrono::Utc>,
+        metadata: serde_json::Value,
+    ) -> anyhow::Result<()> {
+        crate::db::reschedule_job(&*self.db.get().await, name, metadata, when).await
+    }
+}
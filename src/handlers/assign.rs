@@ -198,7 +198,7 @@ pub(super) async fn handle_input(
 ///
 /// Returns the name after the `r?` command, or None if not found.
 fn find_assign_command(ctx: &Context, event: &IssuesEvent) -> Option<String> {
-    let mut input = Input::new(&event.issue.body, vec![&ctx.username]);
+    let mut input = Input::new(&event.issue.body, ctx.trigger_names());
     input.find_map(|command| match command {
         Command::Assign(Ok(AssignCommand::ReviewName { name })) => Some(name),
         _ => None,
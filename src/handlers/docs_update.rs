@@ -1,7 +1,7 @@
 //! A scheduled job to post a PR to update the documentation on rust-lang/rust.
 
 use crate::github::{self, GitTreeEntry, GithubClient, Issue, Repository};
-use crate::jobs::Job;
+use crate::jobs::TypedJob;
 use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -29,16 +29,21 @@ const TITLE: &str = "Update books";
 pub struct DocsUpdateJob;
 
 #[async_trait]
-impl Job for DocsUpdateJob {
-    fn name(&self) -> &'static str {
-        "docs_update"
-    }
+impl TypedJob for DocsUpdateJob {
+    const NAME: &'static str = "docs_update";
+
+    type Metadata = ();
+
+    /// The URL of the PR, if one was opened.
+    type Output = Option<String>;
+
+    const PRIORITY: i16 = -1;
 
     async fn run(
         &self,
-        _ctx: &super::Context,
-        _metadata: &serde_json::Value,
-    ) -> anyhow::Result<()> {
+        ctx: &super::Context,
+        _metadata: Self::Metadata,
+    ) -> anyhow::Result<Option<String>> {
         // Only run every other week. Doing it every week can be a bit noisy, and
         // (rarely) a PR can take longer than a week to merge (like if there are
         // CI issues). `Schedule` does not allow expressing this, so check it
@@ -53,30 +58,29 @@ impl Job for DocsUpdateJob {
         let weeks = duration.num_weeks();
         if weeks % 2 != 0 {
             tracing::trace!("skipping job, this is an odd week");
-            return Ok(());
+            return Ok(None);
         }
 
         tracing::trace!("starting docs-update");
-        docs_update()
+        let pr = docs_update(&ctx.github)
             .await
             .context("failed to process docs update")?;
-        Ok(())
+        Ok(pr.map(|pr| pr.html_url))
     }
 }
 
-pub async fn docs_update() -> Result<Option<Issue>> {
-    let gh = GithubClient::new_from_env();
+pub async fn docs_update(gh: &GithubClient) -> Result<Option<Issue>> {
     let dest_repo = gh.repository(DEST_REPO).await?;
     let work_repo = gh.repository(WORK_REPO).await?;
 
-    let updates = get_submodule_updates(&gh, &dest_repo).await?;
+    let updates = get_submodule_updates(gh, &dest_repo).await?;
     if updates.is_empty() {
         tracing::trace!("no updates this week?");
         return Ok(None);
     }
 
-    create_commit(&gh, &dest_repo, &work_repo, &updates).await?;
-    Ok(Some(create_pr(&gh, &dest_repo, &updates).await?))
+    create_commit(gh, &dest_repo, &work_repo, &updates).await?;
+    Ok(Some(create_pr(gh, &dest_repo, &updates).await?))
 }
 
 struct Update {
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use crate::db::notifications::record_username;
 use crate::github::retrieve_pull_requests;
-use crate::jobs::Job;
+use crate::jobs::TypedJob;
 use crate::ReviewPrefs;
 use anyhow::Context as _;
 use async_trait::async_trait;
@@ -11,12 +11,14 @@ use tokio_postgres::Client as DbClient;
 pub struct PullRequestAssignmentUpdate;
 
 #[async_trait]
-impl Job for PullRequestAssignmentUpdate {
-    fn name(&self) -> &'static str {
-        "pull_request_assignment_update"
-    }
+impl TypedJob for PullRequestAssignmentUpdate {
+    const NAME: &'static str = "pull_request_assignment_update";
+
+    type Metadata = ();
+
+    type Output = ();
 
-    async fn run(&self, ctx: &super::Context, _metadata: &serde_json::Value) -> anyhow::Result<()> {
+    async fn run(&self, ctx: &super::Context, _metadata: Self::Metadata) -> anyhow::Result<()> {
         let db = ctx.db.get().await;
         let gh = &ctx.github;
 
@@ -1,6 +1,6 @@
 use crate::db::rustc_commits;
 use crate::db::rustc_commits::get_missing_commits;
-use crate::jobs::Job;
+use crate::jobs::{MisfirePolicy, TypedJob};
 use crate::{
     github::{self, Event},
     handlers::Context,
@@ -154,12 +154,19 @@ pub async fn synchronize_commits_inner(ctx: &Context, starter: Option<(String, u
 pub struct RustcCommitsJob;
 
 #[async_trait]
-impl Job for RustcCommitsJob {
-    fn name(&self) -> &'static str {
-        "rustc_commits"
-    }
+impl TypedJob for RustcCommitsJob {
+    const NAME: &'static str = "rustc_commits";
+
+    type Metadata = ();
+
+    type Output = ();
+
+    // The next run synchronizes any commits missed in the meantime.
+    const MISFIRE_POLICY: MisfirePolicy = MisfirePolicy::Skip;
+
+    const PRIORITY: i16 = -1;
 
-    async fn run(&self, ctx: &super::Context, _metadata: &serde_json::Value) -> anyhow::Result<()> {
+    async fn run(&self, ctx: &super::Context, _metadata: Self::Metadata) -> anyhow::Result<()> {
         synchronize_commits_inner(ctx, None).await;
         Ok(())
     }
@@ -1,6 +1,5 @@
-use crate::db::schedule_job;
 use crate::github;
-use crate::jobs::Job;
+use crate::jobs::{versioned_metadata, TypedJob};
 use anyhow::Context as _;
 use async_trait::async_trait;
 use chrono::{Datelike, Duration, NaiveTime, TimeZone, Utc};
@@ -13,20 +12,28 @@ const TYPES_MEETINGS_STREAM: u64 = 326132;
 pub struct TypesPlanningMeetingThreadOpenJob;
 
 #[async_trait]
-impl Job for TypesPlanningMeetingThreadOpenJob {
-    fn name(&self) -> &'static str {
-        "types_planning_meeting_thread_open"
-    }
+impl TypedJob for TypesPlanningMeetingThreadOpenJob {
+    const NAME: &'static str = "types_planning_meeting_thread_open";
+
+    type Metadata = ();
+
+    /// The topic of the thread, if one was opened.
+    type Output = Option<String>;
 
-    async fn run(&self, ctx: &super::Context, _metadata: &serde_json::Value) -> anyhow::Result<()> {
+    async fn run(
+        &self,
+        ctx: &super::Context,
+        _metadata: Self::Metadata,
+    ) -> anyhow::Result<Option<String>> {
         // On the last week of the month, we open a thread on zulip for the next Monday
         let today = chrono::Utc::now().date().naive_utc();
         let first_monday = today + chrono::Duration::days(7);
         // We actually schedule for every Monday, so first check if this is the last Monday of the month
         if first_monday.month() == today.month() {
-            return Ok(());
+            return Ok(None);
         }
         let meeting_date_string = first_monday.format("%Y-%m-%d").to_string();
+        let topic = format!("{meeting_date_string} planning meeting");
         let message = format!("\
             Hello @*T-types/meetings*. Monthly planning meeting in one week.\n\
             This is a reminder to update the current [roadmap tracking issues](https://github.com/rust-lang/types-team/issues?q=is%3Aissue+is%3Aopen+label%3Aroadmap-tracking-issue).\n\
@@ -34,7 +41,7 @@ impl Job for TypesPlanningMeetingThreadOpenJob {
         let zulip_req = crate::zulip::MessageApiRequest {
             recipient: crate::zulip::Recipient::Stream {
                 id: TYPES_MEETINGS_STREAM,
-                topic: &format!("{meeting_date_string} planning meeting"),
+                topic: &topic,
             },
             content: &message,
         };
@@ -47,19 +54,19 @@ impl Job for TypesPlanningMeetingThreadOpenJob {
         }
         let thursday_at_noon =
             Utc.from_utc_datetime(&thursday.and_time(NaiveTime::from_hms(12, 0, 0)));
-        let metadata = serde_json::value::to_value(PlanningMeetingUpdatesPingMetadata {
-            date_string: meeting_date_string,
-        })
-        .unwrap();
-        schedule_job(
-            &*ctx.db.get().await,
-            TypesPlanningMeetingUpdatesPing.name(),
-            metadata,
+        let metadata = versioned_metadata::<TypesPlanningMeetingUpdatesPing>(
+            &PlanningMeetingUpdatesPingMetadata {
+                date_string: meeting_date_string,
+            },
+        )?;
+        ctx.schedule_job(
+            TypesPlanningMeetingUpdatesPing::NAME,
             thursday_at_noon,
+            metadata,
         )
         .await?;
 
-        Ok(())
+        Ok(Some(topic))
     }
 }
 
@@ -71,13 +78,16 @@ pub struct PlanningMeetingUpdatesPingMetadata {
 pub struct TypesPlanningMeetingUpdatesPing;
 
 #[async_trait]
-impl Job for TypesPlanningMeetingUpdatesPing {
-    fn name(&self) -> &'static str {
-        "types_planning_meeting_updates_ping"
-    }
+impl TypedJob for TypesPlanningMeetingUpdatesPing {
+    const NAME: &'static str = "types_planning_meeting_updates_ping";
+
+    type Metadata = PlanningMeetingUpdatesPingMetadata;
+
+    type Output = ();
+
+    const PRIORITY: i16 = 1;
 
-    async fn run(&self, ctx: &super::Context, metadata: &serde_json::Value) -> anyhow::Result<()> {
-        let metadata = serde_json::from_value(metadata.clone())?;
+    async fn run(&self, ctx: &super::Context, metadata: Self::Metadata) -> anyhow::Result<()> {
         // On the thursday before the first monday, we want to ping for updates
         request_updates(ctx, metadata).await?;
         Ok(())
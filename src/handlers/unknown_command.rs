@@ -0,0 +1,46 @@
+//! Purpose: When someone mentions the bot followed by a word that isn't a
+//! command, let them know once per issue instead of silently ignoring it.
+//!
+//! Enabled by an `[unknown-command]` section in `triagebot.toml`.
+
+use crate::{
+    config::UnknownCommandConfig, db::issue_data::IssueData, github::Event, handlers::Context,
+};
+use parser::command::unknown::UnknownCommand;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+const UNKNOWN_COMMAND_KEY: &str = "unknown_command";
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct UnknownCommandState {
+    /// Users that have already been told about an unknown command on this issue.
+    hinted_users: HashSet<String>,
+}
+
+pub(super) async fn handle_command(
+    ctx: &Context,
+    _config: &UnknownCommandConfig,
+    event: &Event,
+    cmd: UnknownCommand,
+) -> anyhow::Result<()> {
+    let issue = event.issue().unwrap();
+    let user = event.user().login.to_lowercase();
+
+    let mut client = ctx.db.get().await;
+    let mut state: IssueData<'_, UnknownCommandState> =
+        IssueData::load(&mut client, issue, UNKNOWN_COMMAND_KEY).await?;
+    if !state.data.hinted_users.insert(user) {
+        return Ok(());
+    }
+
+    let hint = format!(
+        "`{}` isn't a command I know, so I didn't do anything. \
+        See [the documentation](https://forge.rust-lang.org/triagebot/index.html) \
+        for the available commands.",
+        cmd.word
+    );
+    issue.post_comment(&ctx.github, &hint).await?;
+    state.save().await?;
+    Ok(())
+}
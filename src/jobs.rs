@@ -3,17 +3,26 @@
 //! Scheduled jobs essentially come in two flavors: automatically repeating
 //! (cron) jobs and one-off jobs.
 //!
-//! The core trait here is the `Job` trait, which *must* define the name of the
-//! job (to be used as an identifier in the database) and the function to run
-//! when the job runs.
+//! The core trait here is the `TypedJob` trait, which *must* define the name of
+//! the job (to be used as an identifier in the database), the type of its
+//! metadata and the function to run when the job runs. Every `TypedJob` is
+//! also a `Job`, the type-erased form kept in the `jobs` registry.
 //!
-//! The metadata is a serde_json::Value
-//! Please refer to https://docs.rs/serde_json/latest/serde_json/value/fn.from_value.html
-//! on how to interpret it as an instance of type T, implementing Serialize/Deserialize.
+//! The metadata is stored as a serde_json::Value and deserialized into the
+//! job's `Metadata` type before it runs; jobs without metadata use `()`.
+//!
+//! Jobs may sit in the database across deploys, so metadata objects carry
+//! their version in a `"v"` field (see `versioned_metadata`). When changing
+//! the shape of a job's metadata, bump its `METADATA_VERSION` and convert
+//! older payloads in `upgrade_metadata`.
 //!
 //! The schedule is a cron::Schedule
 //! Please refer to https://docs.rs/cron/latest/cron/struct.Schedule.html for further info
 //!
+//! Besides `default_jobs`, recurring schedules can be stored in the
+//! `job_schedules` table (see `db::jobs::insert_job_schedule`), either as a
+//! cron expression or as a fixed interval in seconds.
+//!
 //! ## Example, sending a zulip message once a week
 //!
 //! To give an example, let's imagine we want to sends a Zulip message every
@@ -26,14 +35,35 @@
 //!      pub channel: String,
 //!    }
 //!    struct ZulipMessageJob;
-//!    impl Job for ZulipMessageJob { ... }
+//!    impl TypedJob for ZulipMessageJob {
+//!      const NAME: &'static str = "zulip_message";
+//!      type Metadata = ZulipMetadata;
+//!      ...
+//!    }
 //!
 //! (Imagine that this job requires a channel and a message in the metadata.)
 //!
+//! The periodic jobs can also be configured without a rebuild, in a TOML file
+//! named by `TRIAGEBOT_JOBS_CONFIG`. The bot won't start with an invalid one,
+//! and re-reads it on every scheduling pass, keeping the last valid one if it
+//! has since become invalid. For example, to stop updating the docs and look
+//! for rustc commits every 10 minutes:
+//!
+//! ```toml
+//! [jobs.docs_update]
+//! enabled = false
+//!
+//! [jobs.rustc_commits]
+//! schedule = "0 */10 * * * * *"
+//! ```
+//!
+//! Each entry overrides the job's schedule, metadata or whether it runs at
+//! all, and jobs which aren't in `default_jobs` can be added with a schedule.
+//!
 //! If we wanted to have a default scheduled message, we could add the following to
 //! `default_jobs`:
 //!     JobSchedule {
-//!         name: ZulipMessageJob.name(),
+//!         name: ZulipMessageJob::NAME,
 //!         schedule: Schedule::from_str("0 30 11 * * FRI *").unwrap(),
 //!         metadata: serde_json::value::to_value(ZulipMetadata {
 //!             message: "@T-release meeting!".to_string()
@@ -41,10 +71,16 @@
 //!         }).unwrap(),
 //!     }
 
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+pub mod metrics;
+
+use anyhow::Context as _;
 use async_trait::async_trait;
 use cron::Schedule;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{
     db::jobs::JobSchedule,
@@ -58,14 +94,96 @@ use crate::{
     },
 };
 
-/// How often new cron-based jobs will be placed in the queue.
-/// This is the minimum period *between* a single cron task's executions.
+/// How often new cron-based jobs will be placed in the queue. Each pass
+/// queues every run due before `scheduling_horizon`, so jobs may still run
+/// more often than this.
 pub const JOB_SCHEDULING_CADENCE_IN_SECS: u64 = 1800;
 
 /// How often the database is inspected for jobs which need to execute.
 /// This is the granularity at which events will occur.
 pub const JOB_PROCESSING_CADENCE_IN_SECS: u64 = 60;
 
+/// How late a job may start before its `MisfirePolicy` applies, e.g. because
+/// the bot was down when it was due.
+pub const JOB_MISFIRE_THRESHOLD_IN_SECS: i64 = 15 * 60;
+
+/// How long a job claimed by one instance is hidden from the others. This
+/// should comfortably exceed the time a job takes to run.
+pub const JOB_LOCK_DURATION_IN_SECS: u64 = 60 * 60;
+
+/// How long a job may run before it is abandoned and counted as failed, unless
+/// it sets its own `TypedJob::TIMEOUT`.
+pub const JOB_DEFAULT_TIMEOUT_IN_SECS: u64 = 15 * 60;
+
+/// The most jobs run in a single pass of the job runner, so that a burst of
+/// jobs due at the same time is spread over several passes instead of using
+/// up the GitHub rate limit at once.
+pub const JOB_MAX_PER_PASS: i64 = 20;
+
+/// The upper bound of the random delay between two jobs in the same pass.
+pub const JOB_MAX_JITTER_IN_MILLIS: u64 = 2000;
+
+/// How many jobs run at the same time unless `TRIAGEBOT_JOB_CONCURRENCY` is
+/// set.
+pub const JOB_DEFAULT_CONCURRENCY: usize = 4;
+
+/// How long running jobs are given to finish when the bot shuts down.
+pub const JOB_SHUTDOWN_GRACE_PERIOD_IN_SECS: u64 = 25;
+
+/// How many times a job is attempted before it is moved to the dead letter
+/// queue.
+pub const MAX_JOB_ATTEMPTS: i32 = 5;
+
+/// The delay before the first retry of a failed job, doubling on every
+/// further attempt.
+pub const JOB_RETRY_BASE_DELAY_IN_SECS: i64 = 5 * 60;
+
+static JOBS_PAUSED: AtomicBool = AtomicBool::new(false);
+static JOBS_SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Stops the job runner from starting new jobs, e.g. during an incident or a
+/// migration. Jobs keep being scheduled, and running jobs are left to finish.
+/// This isn't persisted, so a restarted bot runs jobs again.
+pub fn pause_jobs() {
+    JOBS_PAUSED.store(true, Ordering::SeqCst);
+}
+
+pub fn resume_jobs() {
+    JOBS_PAUSED.store(false, Ordering::SeqCst);
+}
+
+pub fn jobs_paused() -> bool {
+    JOBS_PAUSED.load(Ordering::SeqCst)
+}
+
+/// Stops the job runner from starting new jobs for good, as the bot is
+/// shutting down. Unlike `pause_jobs`, this can't be undone.
+pub fn shut_down_jobs() {
+    JOBS_SHUTTING_DOWN.store(true, Ordering::SeqCst);
+}
+
+pub fn jobs_shutting_down() -> bool {
+    JOBS_SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// How far ahead of `now` a scheduling pass queues the runs of recurring jobs:
+/// up to the next pass, with another cadence to spare in case it is late.
+pub fn scheduling_horizon(now: chrono::DateTime<chrono::Utc>) -> chrono::DateTime<chrono::Utc> {
+    now + chrono::Duration::seconds(2 * JOB_SCHEDULING_CADENCE_IN_SECS as i64)
+}
+
+/// How long to wait before retrying a job that has failed `attempts` times,
+/// or `None` if it shouldn't be retried anymore.
+pub fn retry_delay(attempts: i32) -> Option<chrono::Duration> {
+    if attempts >= MAX_JOB_ATTEMPTS {
+        return None;
+    }
+    let exponent = attempts.saturating_sub(1).clamp(0, 30) as u32;
+    Some(chrono::Duration::seconds(
+        JOB_RETRY_BASE_DELAY_IN_SECS.saturating_mul(1 << exponent),
+    ))
+}
+
 // The default jobs to schedule, repeatedly.
 pub fn jobs() -> Vec<Box<dyn Job + Send + Sync>> {
     vec![
@@ -79,19 +197,19 @@ pub fn jobs() -> Vec<Box<dyn Job + Send + Sync>> {
 pub fn default_jobs() -> Vec<JobSchedule> {
     vec![
         JobSchedule {
-            name: DocsUpdateJob.name(),
+            name: DocsUpdateJob::NAME,
             // Around 9am Pacific time on every Monday.
             schedule: Schedule::from_str("0 00 17 * * Mon *").unwrap(),
             metadata: serde_json::Value::Null,
         },
         JobSchedule {
-            name: RustcCommitsJob.name(),
+            name: RustcCommitsJob::NAME,
             // Every 30 minutes...
-            schedule: Schedule::from_str("* 0,30 * * * * *").unwrap(),
+            schedule: Schedule::from_str("0 0,30 * * * * *").unwrap(),
             metadata: serde_json::Value::Null,
         },
         JobSchedule {
-            name: TypesPlanningMeetingThreadOpenJob.name(),
+            name: TypesPlanningMeetingThreadOpenJob::NAME,
             // We want last Monday of every month, but cron unfortunately doesn't support that
             // Instead, every Monday and we can check
             schedule: Schedule::from_str("0 0 0 ? * MON *").unwrap(),
@@ -100,11 +218,373 @@ pub fn default_jobs() -> Vec<JobSchedule> {
     ]
 }
 
+/// What to do with a job that is overdue by more than
+/// `JOB_MISFIRE_THRESHOLD_IN_SECS` when the runner gets to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MisfirePolicy {
+    /// Run the job as if it were on time.
+    Run,
+    /// Drop this run of the job.
+    Skip,
+    /// Run the job with `"late": true` added to its metadata, if the metadata
+    /// is an object. Jobs can pick it up with a `#[serde(default)] late: bool`
+    /// field.
+    RunLate,
+}
+
+/// The contents of the `TRIAGEBOT_JOBS_CONFIG` file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobsConfig {
+    #[serde(default)]
+    pub jobs: HashMap<String, PeriodicJobConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PeriodicJobConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// A cron expression, replacing the default schedule.
+    pub schedule: Option<String>,
+    /// Replaces the default metadata.
+    pub metadata: Option<serde_json::Value>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Clone)]
+pub struct PeriodicJobs {
+    pub enabled: Vec<JobSchedule>,
+    /// The name and metadata of the jobs turned off in the jobs config, whose
+    /// runs queued before then are cancelled.
+    pub disabled: Vec<(&'static str, serde_json::Value)>,
+}
+
+/// The periodic jobs to schedule: `default_jobs`, adjusted by the file named
+/// by `TRIAGEBOT_JOBS_CONFIG` if it is set.
+pub fn load_periodic_jobs() -> anyhow::Result<PeriodicJobs> {
+    let path = match std::env::var_os("TRIAGEBOT_JOBS_CONFIG") {
+        Some(path) => path,
+        None => {
+            return Ok(PeriodicJobs {
+                enabled: default_jobs(),
+                disabled: Vec::new(),
+            })
+        }
+    };
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read jobs config {:?}", path))?;
+    let config: JobsConfig = toml::from_str(&contents)
+        .with_context(|| format!("failed to parse jobs config {:?}", path))?;
+    periodic_jobs(&config)
+}
+
+fn periodic_jobs(config: &JobsConfig) -> anyhow::Result<PeriodicJobs> {
+    let parse_schedule = |name: &str, schedule: &str| {
+        Schedule::from_str(schedule)
+            .with_context(|| format!("invalid schedule `{}` for job `{}`", schedule, name))
+    };
+
+    let mut schedules = Vec::new();
+    let mut disabled = Vec::new();
+    for mut job in default_jobs() {
+        if let Some(job_config) = config.jobs.get(job.name) {
+            if let Some(metadata) = &job_config.metadata {
+                job.metadata = metadata.clone();
+            }
+            if !job_config.enabled {
+                disabled.push((job.name, job.metadata));
+                continue;
+            }
+            if let Some(schedule) = &job_config.schedule {
+                job.schedule = parse_schedule(job.name, schedule)?;
+            }
+        }
+        schedules.push(job);
+    }
+
+    let all_jobs = jobs();
+    for (name, job_config) in &config.jobs {
+        // Default jobs were handled above.
+        if schedules.iter().any(|job| job.name == name)
+            || disabled.iter().any(|(disabled, _)| disabled == name)
+        {
+            continue;
+        }
+        let job = all_jobs
+            .iter()
+            .find(|job| job.name() == name)
+            .with_context(|| format!("unknown job `{}` in jobs config", name))?;
+        if !job_config.enabled {
+            disabled.push((
+                job.name(),
+                job_config
+                    .metadata
+                    .clone()
+                    .unwrap_or(serde_json::Value::Null),
+            ));
+            continue;
+        }
+        let schedule = job_config
+            .schedule
+            .as_ref()
+            .with_context(|| format!("job `{}` needs a schedule in jobs config", name))?;
+        schedules.push(JobSchedule {
+            name: job.name(),
+            schedule: parse_schedule(name, schedule)?,
+            metadata: job_config
+                .metadata
+                .clone()
+                .unwrap_or(serde_json::Value::Null),
+        });
+    }
+
+    Ok(PeriodicJobs {
+        enabled: schedules,
+        disabled,
+    })
+}
+
 #[async_trait]
 pub trait Job {
-    fn name(&self) -> &str;
+    fn name(&self) -> &'static str;
 
-    async fn run(&self, ctx: &Context, metadata: &serde_json::Value) -> anyhow::Result<()>;
+    fn misfire_policy(&self) -> MisfirePolicy;
+
+    fn timeout(&self) -> std::time::Duration;
+
+    fn priority(&self) -> i16;
+
+    /// Runs the job, returning its output to record in the execution history.
+    async fn run(
+        &self,
+        ctx: &Context,
+        metadata: &serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value>;
+}
+
+#[async_trait]
+pub trait TypedJob {
+    const NAME: &'static str;
+
+    type Metadata: Serialize + DeserializeOwned + Send;
+
+    /// What the job did, e.g. the comment it posted, stored alongside its
+    /// execution in `jobs_history` to help explain the bot's actions later.
+    type Output: Serialize + Send;
+
+    /// The version of `Metadata` written by `versioned_metadata`.
+    const METADATA_VERSION: u64 = 1;
+
+    const MISFIRE_POLICY: MisfirePolicy = MisfirePolicy::Run;
+
+    /// When more jobs are due than can run at once, those with a higher
+    /// priority run first. User-facing jobs should use a positive priority and
+    /// background syncs a negative one.
+    const PRIORITY: i16 = 0;
+
+    const TIMEOUT: std::time::Duration =
+        std::time::Duration::from_secs(JOB_DEFAULT_TIMEOUT_IN_SECS);
+
+    async fn run(&self, ctx: &Context, metadata: Self::Metadata) -> anyhow::Result<Self::Output>;
+
+    /// Converts metadata of `version` (without its `"v"` field) to
+    /// `version + 1`.
+    fn upgrade_metadata(
+        version: u64,
+        _metadata: serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        anyhow::bail!("cannot upgrade metadata from version {}", version)
+    }
+}
+
+/// Serializes the metadata of a `T` job, tagged with its current version.
+pub fn versioned_metadata<T: TypedJob>(
+    metadata: &T::Metadata,
+) -> anyhow::Result<serde_json::Value> {
+    let mut value = serde_json::to_value(metadata)?;
+    if let Some(object) = value.as_object_mut() {
+        object.insert("v".to_string(), T::METADATA_VERSION.into());
+    }
+    Ok(value)
+}
+
+/// Deserializes the metadata of a `T` job, first upgrading it if it was
+/// written by an older version. Metadata without a version is version 1.
+fn decode_metadata<T: TypedJob>(metadata: &serde_json::Value) -> anyhow::Result<T::Metadata> {
+    let mut metadata = metadata.clone();
+    let mut version = match metadata.as_object_mut().and_then(|o| o.remove("v")) {
+        Some(v) => v
+            .as_u64()
+            .with_context(|| format!("invalid metadata version {}", v))?,
+        None => 1,
+    };
+    if version > T::METADATA_VERSION {
+        anyhow::bail!(
+            "metadata version {} is newer than the supported version {}",
+            version,
+            T::METADATA_VERSION
+        );
+    }
+    while version < T::METADATA_VERSION {
+        metadata = T::upgrade_metadata(version, metadata)?;
+        version += 1;
+    }
+    Ok(serde_json::from_value(metadata)?)
+}
+
+#[async_trait]
+impl<T> Job for T
+where
+    T: TypedJob + Sync,
+{
+    fn name(&self) -> &'static str {
+        T::NAME
+    }
+
+    fn misfire_policy(&self) -> MisfirePolicy {
+        T::MISFIRE_POLICY
+    }
+
+    fn timeout(&self) -> std::time::Duration {
+        T::TIMEOUT
+    }
+
+    fn priority(&self) -> i16 {
+        T::PRIORITY
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        metadata: &serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        let metadata = decode_metadata::<T>(metadata)
+            .with_context(|| format!("invalid metadata for job `{}`", T::NAME))?;
+        let output = TypedJob::run(self, ctx, metadata).await?;
+        // The job already ran, so failing here would only make it run again.
+        Ok(serde_json::to_value(&output).unwrap_or_else(|e| {
+            tracing::warn!("failed to serialize output of job `{}`: {:?}", T::NAME, e);
+            serde_json::Value::Null
+        }))
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct VersionedMetadata {
+    issue: u64,
+    reason: String,
+}
+
+#[cfg(test)]
+struct VersionedJob;
+
+#[cfg(test)]
+#[async_trait]
+impl TypedJob for VersionedJob {
+    const NAME: &'static str = "versioned_job";
+
+    type Metadata = VersionedMetadata;
+
+    type Output = ();
+
+    const METADATA_VERSION: u64 = 2;
+
+    async fn run(&self, _ctx: &Context, _metadata: VersionedMetadata) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn upgrade_metadata(
+        version: u64,
+        mut metadata: serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        assert_eq!(version, 1);
+        // Version 1 didn't have a reason.
+        metadata["reason"] = "unknown".into();
+        Ok(metadata)
+    }
+}
+
+#[test]
+fn metadata_versions() {
+    let metadata = VersionedMetadata {
+        issue: 1,
+        reason: "done".to_string(),
+    };
+    let value = versioned_metadata::<VersionedJob>(&metadata).unwrap();
+    assert_eq!(value["v"], 2);
+    assert_eq!(decode_metadata::<VersionedJob>(&value).unwrap(), metadata);
+
+    let legacy = serde_json::json!({ "issue": 1 });
+    assert_eq!(
+        decode_metadata::<VersionedJob>(&legacy).unwrap(),
+        VersionedMetadata {
+            issue: 1,
+            reason: "unknown".to_string()
+        }
+    );
+
+    let newer = serde_json::json!({ "v": 3, "issue": 1 });
+    assert!(decode_metadata::<VersionedJob>(&newer).is_err());
+}
+
+#[test]
+fn jobs_config() {
+    let config: JobsConfig = toml::from_str(
+        r#"
+        [jobs.docs_update]
+        enabled = false
+
+        [jobs.rustc_commits]
+        schedule = "0 */10 * * * * *"
+
+        [jobs.types_planning_meeting_updates_ping]
+        schedule = "0 0 12 * * THU *"
+        metadata = { date_string = "2023-01-02" }
+        "#,
+    )
+    .unwrap();
+    let periodic = periodic_jobs(&config).unwrap();
+    let schedules = periodic.enabled;
+    let names: Vec<_> = schedules.iter().map(|job| job.name).collect();
+    assert_eq!(
+        names,
+        [
+            RustcCommitsJob::NAME,
+            TypesPlanningMeetingThreadOpenJob::NAME,
+            TypesPlanningMeetingUpdatesPing::NAME,
+        ]
+    );
+    assert_eq!(schedules[0].schedule.to_string(), "0 */10 * * * * *");
+    assert_eq!(
+        schedules[2].metadata,
+        serde_json::json!({ "date_string": "2023-01-02" })
+    );
+    assert_eq!(
+        periodic.disabled,
+        [(DocsUpdateJob::NAME, serde_json::Value::Null)]
+    );
+
+    let unknown: JobsConfig = toml::from_str(
+        r#"
+        [jobs.unknown]
+        schedule = "0 0 12 * * THU *"
+        "#,
+    )
+    .unwrap();
+    assert!(periodic_jobs(&unknown).is_err());
+}
+
+#[test]
+fn retry_backoff() {
+    assert_eq!(retry_delay(1), Some(chrono::Duration::minutes(5)));
+    assert_eq!(retry_delay(2), Some(chrono::Duration::minutes(10)));
+    assert_eq!(retry_delay(4), Some(chrono::Duration::minutes(40)));
+    assert_eq!(retry_delay(MAX_JOB_ATTEMPTS), None);
 }
 
 #[test]
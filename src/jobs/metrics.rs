@@ -0,0 +1,175 @@
+//! Metrics about the job queue, rendered in the Prometheus text format by the
+//! `/admin/metrics` endpoint.
+//!
+//! Execution counts and latencies are kept in memory since the process
+//! started, while the queue depth is read from the database on every scrape.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds, in seconds, of the job duration histogram buckets.
+const DURATION_BUCKETS: &[f64] = &[0.1, 1.0, 10.0, 60.0, 300.0, 1800.0];
+
+#[derive(Default)]
+struct JobStats {
+    executions: u64,
+    failures: u64,
+    duration_sum: f64,
+    /// Number of executions per bucket in `DURATION_BUCKETS` (not cumulative).
+    duration_buckets: [u64; DURATION_BUCKETS.len()],
+}
+
+lazy_static::lazy_static! {
+    static ref JOB_STATS: Mutex<BTreeMap<String, JobStats>> = Mutex::new(BTreeMap::new());
+}
+
+/// The state of the queue for a single job name.
+#[derive(Debug)]
+pub struct QueueDepth {
+    pub name: String,
+    /// Jobs which are going to run.
+    pub pending: i64,
+    /// Pending jobs which are past their scheduled time.
+    pub overdue: i64,
+    /// Jobs which failed for good, including those in the dead letter queue.
+    pub failed: i64,
+}
+
+pub fn record_execution(name: &str, duration: Duration, succeeded: bool) {
+    let mut stats = JOB_STATS.lock().unwrap();
+    let stats = stats.entry(name.to_string()).or_default();
+    let secs = duration.as_secs_f64();
+    stats.executions += 1;
+    if !succeeded {
+        stats.failures += 1;
+    }
+    stats.duration_sum += secs;
+    if let Some(bucket) = DURATION_BUCKETS.iter().position(|&le| secs <= le) {
+        stats.duration_buckets[bucket] += 1;
+    }
+}
+
+pub fn render(queue: &[QueueDepth]) -> String {
+    let mut out = String::new();
+    let gauges: [(&str, &str, fn(&QueueDepth) -> i64); 3] = [
+        ("triagebot_jobs_pending", "Jobs waiting to run.", |q| {
+            q.pending
+        }),
+        (
+            "triagebot_jobs_overdue",
+            "Pending jobs past their scheduled time.",
+            |q| q.overdue,
+        ),
+        (
+            "triagebot_jobs_failed",
+            "Jobs which failed for good.",
+            |q| q.failed,
+        ),
+    ];
+    for (metric, help, value) in gauges {
+        writeln!(out, "# HELP {metric} {help}\n# TYPE {metric} gauge").unwrap();
+        for q in queue {
+            writeln!(out, "{metric}{{job=\"{}\"}} {}", escape(&q.name), value(q)).unwrap();
+        }
+    }
+
+    let stats = JOB_STATS.lock().unwrap();
+    out.push_str(
+        "# HELP triagebot_job_executions_total Job executions since startup.\n\
+        # TYPE triagebot_job_executions_total counter\n",
+    );
+    for (name, s) in stats.iter() {
+        let name = escape(name);
+        writeln!(
+            out,
+            "triagebot_job_executions_total{{job=\"{name}\"}} {}",
+            s.executions
+        )
+        .unwrap();
+    }
+    out.push_str(
+        "# HELP triagebot_job_failures_total Failed job executions since startup.\n\
+        # TYPE triagebot_job_failures_total counter\n",
+    );
+    for (name, s) in stats.iter() {
+        let name = escape(name);
+        writeln!(
+            out,
+            "triagebot_job_failures_total{{job=\"{name}\"}} {}",
+            s.failures
+        )
+        .unwrap();
+    }
+    out.push_str(
+        "# HELP triagebot_job_duration_seconds How long job executions took.\n\
+        # TYPE triagebot_job_duration_seconds histogram\n",
+    );
+    for (name, s) in stats.iter() {
+        let name = escape(name);
+        let mut cumulative = 0;
+        for (le, count) in DURATION_BUCKETS.iter().zip(s.duration_buckets) {
+            cumulative += count;
+            writeln!(
+                out,
+                "triagebot_job_duration_seconds_bucket{{job=\"{name}\",le=\"{le}\"}} {cumulative}"
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "triagebot_job_duration_seconds_bucket{{job=\"{name}\",le=\"+Inf\"}} {}",
+            s.executions
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "triagebot_job_duration_seconds_sum{{job=\"{name}\"}} {}",
+            s.duration_sum
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "triagebot_job_duration_seconds_count{{job=\"{name}\"}} {}",
+            s.executions
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Escapes a label value for the Prometheus text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[test]
+fn render_metrics() {
+    record_execution("metrics_test", Duration::from_millis(500), true);
+    record_execution("metrics_test", Duration::from_secs(2), false);
+    let out = render(&[QueueDepth {
+        name: "metrics_test".to_string(),
+        pending: 3,
+        overdue: 1,
+        failed: 0,
+    }]);
+    for line in [
+        "triagebot_jobs_pending{job=\"metrics_test\"} 3",
+        "triagebot_jobs_overdue{job=\"metrics_test\"} 1",
+        "triagebot_job_executions_total{job=\"metrics_test\"} 2",
+        "triagebot_job_failures_total{job=\"metrics_test\"} 1",
+        "triagebot_job_duration_seconds_bucket{job=\"metrics_test\",le=\"0.1\"} 0",
+        "triagebot_job_duration_seconds_bucket{job=\"metrics_test\",le=\"1\"} 1",
+        "triagebot_job_duration_seconds_bucket{job=\"metrics_test\",le=\"10\"} 2",
+        "triagebot_job_duration_seconds_count{job=\"metrics_test\"} 2",
+    ] {
+        assert!(
+            out.lines().any(|l| l == line),
+            "missing `{line}` in:\n{out}"
+        );
+    }
+}
@@ -6,15 +6,19 @@ use futures::StreamExt;
 use hyper::{header, Body, Request, Response, Server, StatusCode};
 use route_recognizer::Router;
 use std::{env, net::SocketAddr, sync::Arc};
-use tokio::{task, time};
+use tokio::{signal, sync::Semaphore, task, time};
 use tower::{Service, ServiceExt};
 use tracing as log;
 use tracing::Instrument;
 use triagebot::handlers::pull_requests_assignment_update::PullRequestAssignmentUpdate;
 use triagebot::jobs::{
-    default_jobs, Job, JOB_PROCESSING_CADENCE_IN_SECS, JOB_SCHEDULING_CADENCE_IN_SECS,
+    jobs_paused, jobs_shutting_down, load_periodic_jobs, pause_jobs, resume_jobs, shut_down_jobs,
+    Job, JOB_DEFAULT_CONCURRENCY, JOB_PROCESSING_CADENCE_IN_SECS, JOB_SCHEDULING_CADENCE_IN_SECS,
+    JOB_SHUTDOWN_GRACE_PERIOD_IN_SECS,
+};
+use triagebot::{
+    db, github, handlers::Context, jobs::PeriodicJobs, notification_listing, payload, EventName,
 };
-use triagebot::{db, github, handlers::Context, notification_listing, payload, EventName};
 
 async fn handle_agenda_request(req: String) -> anyhow::Result<String> {
     if req == "/agenda/lang/triage" {
@@ -85,9 +89,13 @@ async fn serve_req(
     }
 
     if req.uri.path() == "/" {
+        let mut status = String::from("Triagebot is awaiting triage.");
+        if jobs_paused() {
+            status.push_str("\nScheduled jobs are paused.");
+        }
         return Ok(Response::builder()
             .status(StatusCode::OK)
-            .body(Body::from("Triagebot is awaiting triage."))
+            .body(Body::from(status))
             .unwrap());
     }
     if req.uri.path() == "/bors-commit-list" {
@@ -153,6 +161,210 @@ async fn serve_req(
             .body(Body::from(triagebot::zulip::respond(&ctx, req).await))
             .unwrap());
     }
+    if req.uri.path() == "/admin/jobs" {
+        if let Some(res) = check_admin_auth(&req.headers) {
+            return Ok(res);
+        }
+        if req.method == hyper::Method::POST {
+            let mut c = body_stream;
+            let mut payload = Vec::new();
+            while let Some(chunk) = c.next().await {
+                let chunk = chunk?;
+                payload.extend_from_slice(&chunk);
+            }
+            let job: EnqueueJobRequest = match serde_json::from_slice(&payload) {
+                Ok(job) => job,
+                Err(e) => {
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Body::from(format!(
+                            "Did not send valid JSON request: {}",
+                            e
+                        )))
+                        .unwrap());
+                }
+            };
+            let when = job.scheduled_at.unwrap_or_else(chrono::Utc::now);
+            let res = db::schedule_job(&*ctx.db.get().await, &job.name, job.metadata, when).await;
+            return match res {
+                Ok(()) => Ok(Response::builder()
+                    .status(StatusCode::CREATED)
+                    .body(Body::from(format!("scheduled {} at {}", job.name, when)))
+                    .unwrap()),
+                Err(e) => Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Body::from(format!("{:?}", e)))
+                    .unwrap()),
+            };
+        }
+        if req.method != hyper::Method::GET {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "GET, POST")
+                .body(Body::empty())
+                .unwrap());
+        }
+        let res = db::jobs::get_pending_jobs(&*ctx.db.get().await).await;
+        let res = match res {
+            Ok(r) => r,
+            Err(e) => {
+                return Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from(format!("{:?}", e)))
+                    .unwrap());
+            }
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_string(&res).unwrap()))
+            .unwrap());
+    }
+    if let Some(id) = req
+        .uri
+        .path()
+        .strip_prefix("/admin/jobs/")
+        .and_then(|rest| rest.strip_suffix("/run-now"))
+    {
+        if let Some(res) = check_admin_auth(&req.headers) {
+            return Ok(res);
+        }
+        if req.method != hyper::Method::POST {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "POST")
+                .body(Body::empty())
+                .unwrap());
+        }
+        let id = match uuid::Uuid::parse_str(id) {
+            Ok(id) => id,
+            Err(_) => {
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Body::from("Invalid job id"))
+                    .unwrap());
+            }
+        };
+        return match db::jobs::run_job_now(&*ctx.db.get().await, &id).await {
+            Ok(true) => Ok(Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::from(
+                    "job will run on the next pass of the job runner",
+                ))
+                .unwrap()),
+            Ok(false) => Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("No such job"))
+                .unwrap()),
+            Err(e) => Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(format!("{:?}", e)))
+                .unwrap()),
+        };
+    }
+    if req.uri.path() == "/admin/jobs/pause" || req.uri.path() == "/admin/jobs/resume" {
+        if let Some(res) = check_admin_auth(&req.headers) {
+            return Ok(res);
+        }
+        if req.method != hyper::Method::POST {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "POST")
+                .body(Body::empty())
+                .unwrap());
+        }
+        let body = if req.uri.path() == "/admin/jobs/pause" {
+            pause_jobs();
+            log::warn!("scheduled jobs paused");
+            "Scheduled jobs are paused"
+        } else {
+            resume_jobs();
+            log::warn!("scheduled jobs resumed");
+            "Scheduled jobs are running"
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::from(body))
+            .unwrap());
+    }
+    if req.uri.path() == "/admin/jobs/dead-letter" {
+        if let Some(res) = check_admin_auth(&req.headers) {
+            return Ok(res);
+        }
+        let res = db::jobs::get_dead_letter_jobs(&*ctx.db.get().await).await;
+        let res = match res {
+            Ok(r) => r,
+            Err(e) => {
+                return Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from(format!("{:?}", e)))
+                    .unwrap());
+            }
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_string(&res).unwrap()))
+            .unwrap());
+    }
+    if req.uri.path() == "/admin/jobs/history" {
+        if let Some(res) = check_admin_auth(&req.headers) {
+            return Ok(res);
+        }
+        let mut name = None;
+        let mut failed_only = false;
+        let mut limit = 100;
+        if let Some(query) = req.uri.query() {
+            for (k, v) in url::form_urlencoded::parse(query.as_bytes()) {
+                match &*k {
+                    "name" => name = Some(v.into_owned()),
+                    "failed" => failed_only = v == "true",
+                    "limit" => limit = v.parse().unwrap_or(limit),
+                    _ => {}
+                }
+            }
+        }
+        let res = db::jobs_history::get_job_executions(
+            &*ctx.db.get().await,
+            name.as_deref(),
+            failed_only,
+            limit,
+        )
+        .await;
+        let res = match res {
+            Ok(r) => r,
+            Err(e) => {
+                return Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from(format!("{:?}", e)))
+                    .unwrap());
+            }
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_string(&res).unwrap()))
+            .unwrap());
+    }
+    if req.uri.path() == "/admin/metrics" {
+        if let Some(res) = check_admin_auth(&req.headers) {
+            return Ok(res);
+        }
+        let queue = match db::jobs::get_queue_depth(&*ctx.db.get().await).await {
+            Ok(queue) => queue,
+            Err(e) => {
+                return Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from(format!("{:?}", e)))
+                    .unwrap());
+            }
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(Body::from(triagebot::jobs::metrics::render(&queue)))
+            .unwrap());
+    }
     if req.uri.path() != "/github-hook" {
         return Ok(Response::builder()
             .status(StatusCode::NOT_FOUND)
@@ -241,6 +453,52 @@ async fn serve_req(
     }
 }
 
+/// The body of a `POST /admin/jobs` request, enqueueing a job.
+#[derive(serde::Deserialize)]
+struct EnqueueJobRequest {
+    name: String,
+    #[serde(default)]
+    metadata: serde_json::Value,
+    /// Defaults to now.
+    scheduled_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Checks that a request to an `/admin` endpoint carries the
+/// `TRIAGEBOT_ADMIN_TOKEN` as a bearer token, returning the response to send
+/// otherwise. The endpoints don't exist if no token is configured.
+fn check_admin_auth(headers: &hyper::HeaderMap) -> Option<Response<Body>> {
+    let token = match env::var("TRIAGEBOT_ADMIN_TOKEN") {
+        Ok(token) if !token.is_empty() => token,
+        _ => {
+            return Some(
+                Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        }
+    };
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match provided {
+        Some(provided)
+            if provided.len() == token.len()
+                && openssl::memcmp::eq(provided.as_bytes(), token.as_bytes()) =>
+        {
+            None
+        }
+        _ => Some(
+            Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(header::WWW_AUTHENTICATE, "Bearer")
+                .body(Body::from("Invalid or missing admin token"))
+                .unwrap(),
+        ),
+    }
+}
+
 async fn run_server(addr: SocketAddr) -> anyhow::Result<()> {
     let pool = db::ClientPool::new();
     db::run_migrations(&*pool.get().await)
@@ -248,15 +506,24 @@ async fn run_server(addr: SocketAddr) -> anyhow::Result<()> {
         .context("database migrations")?;
 
     let gh = github::GithubClient::new_from_env();
-    let oc = octocrab::OctocrabBuilder::new()
-        .personal_token(github::default_token_from_env())
-        .build()
-        .expect("Failed to build octograb.");
+    let oc = match gh.app() {
+        Some(app) => octocrab::OctocrabBuilder::new()
+            .app(app.app_id.into(), app.key().clone())
+            .build()
+            .expect("Failed to build octograb.")
+            .installation(app.installation_id.into()),
+        None => octocrab::OctocrabBuilder::new()
+            .personal_token(github::default_token_from_env())
+            .build()
+            .expect("Failed to build octograb."),
+    };
+    let username = std::env::var("TRIAGEBOT_USERNAME").or_else(|err| match err {
+        std::env::VarError::NotPresent => Ok("rustbot".to_owned()),
+        err => Err(err),
+    })?;
     let ctx = Arc::new(Context {
-        username: std::env::var("TRIAGEBOT_USERNAME").or_else(|err| match err {
-            std::env::VarError::NotPresent => Ok("rustbot".to_owned()),
-            err => Err(err),
-        })?,
+        trigger_names: trigger_names(&username),
+        username,
         db: pool,
         github: gh,
         octocrab: oc,
@@ -270,9 +537,12 @@ async fn run_server(addr: SocketAddr) -> anyhow::Result<()> {
     }
 
     // Run all jobs that have a schedule (recurring jobs)
+    let concurrency = job_concurrency();
+    let workers = Arc::new(Semaphore::new(concurrency));
     if !is_scheduled_jobs_disabled() {
-        spawn_job_scheduler();
-        spawn_job_runner(ctx.clone());
+        let periodic_jobs = load_periodic_jobs().context("loading jobs config")?;
+        spawn_job_scheduler(periodic_jobs);
+        spawn_job_runner(ctx.clone(), workers.clone());
     }
 
     let agenda = tower::ServiceBuilder::new()
@@ -291,8 +561,9 @@ async fn run_server(addr: SocketAddr) -> anyhow::Result<()> {
         })
         .service_fn(handle_agenda_request);
 
+    let svc_ctx = ctx.clone();
     let svc = hyper::service::make_service_fn(move |_conn| {
-        let ctx = ctx.clone();
+        let ctx = svc_ctx.clone();
         let agenda = agenda.clone();
         async move {
             Ok::<_, hyper::Error>(hyper::service::service_fn(move |req| {
@@ -313,9 +584,28 @@ async fn run_server(addr: SocketAddr) -> anyhow::Result<()> {
     });
     log::info!("Listening on http://{}", addr);
 
-    let serve_future = Server::bind(&addr).serve(svc);
+    let mut sigterm = signal::unix::signal(signal::unix::SignalKind::terminate())
+        .context("listening for SIGTERM")?;
+    let serve_future = Server::bind(&addr)
+        .serve(svc)
+        .with_graceful_shutdown(async move {
+            sigterm.recv().await;
+            log::info!("received SIGTERM, shutting down");
+        });
 
     serve_future.await?;
+
+    shut_down_jobs();
+    if !is_scheduled_jobs_disabled() {
+        db::drain_jobs(
+            &*ctx.db.get().await,
+            &workers,
+            concurrency,
+            time::Duration::from_secs(JOB_SHUTDOWN_GRACE_PERIOD_IN_SECS),
+        )
+        .await
+        .context("draining jobs")?;
+    }
     Ok(())
 }
 
@@ -354,9 +644,13 @@ async fn spawn_job_oneoffs(ctx: Arc<Context>) {
 /// The scheduler wakes up every `JOB_SCHEDULING_CADENCE_IN_SECS` seconds to
 /// check if there are any jobs ready to run. Jobs get inserted into the the
 /// database which acts as a queue.
-fn spawn_job_scheduler() {
+///
+/// `periodic_jobs` are the ones loaded at startup. The jobs config is reloaded
+/// on every pass, and the last valid one is kept if it has become invalid.
+fn spawn_job_scheduler(periodic_jobs: PeriodicJobs) {
     task::spawn(async move {
         loop {
+            let mut periodic_jobs = periodic_jobs.clone();
             let res = task::spawn(async move {
                 let pool = db::ClientPool::new();
                 let mut interval =
@@ -364,10 +658,26 @@ fn spawn_job_scheduler() {
 
                 loop {
                     interval.tick().await;
-                    db::schedule_jobs(&*pool.get().await, default_jobs())
+                    let db = pool.get().await;
+                    match load_periodic_jobs() {
+                        Ok(jobs) => periodic_jobs = jobs,
+                        Err(e) => tracing::error!(
+                            "failed to reload jobs config, keeping the last one: {:?}",
+                            e
+                        ),
+                    }
+                    db::schedule_jobs(&*db, periodic_jobs.enabled.clone())
                         .await
                         .context("database schedule jobs")
                         .unwrap();
+                    db::cancel_disabled_jobs(&*db, &periodic_jobs.disabled)
+                        .await
+                        .context("database cancel disabled jobs")
+                        .unwrap();
+                    db::schedule_stored_jobs(&*db)
+                        .await
+                        .context("database schedule stored jobs")
+                        .unwrap();
                 }
             });
 
@@ -384,15 +694,21 @@ fn spawn_job_scheduler() {
 }
 
 /// Spawns a background tokio task which runs continuously to run scheduled
-/// jobs.
+/// jobs, at most `TRIAGEBOT_JOB_CONCURRENCY` at a time, unless they were
+/// paused through `/admin/jobs/pause`. It stops for good when the bot shuts
+/// down.
 ///
 /// The runner wakes up every `JOB_PROCESSING_CADENCE_IN_SECS` seconds to
 /// check if any jobs have been put into the queue by the scheduler. They
 /// will get popped off the queue and run if any are found.
-fn spawn_job_runner(ctx: Arc<Context>) {
+///
+/// `workers` is shared across restarts of the runner, as jobs keep running
+/// on their own tasks if it dies.
+fn spawn_job_runner(ctx: Arc<Context>, workers: Arc<Semaphore>) {
     task::spawn(async move {
         loop {
             let ctx = ctx.clone();
+            let workers = workers.clone();
             let res = task::spawn(async move {
                 let pool = db::ClientPool::new();
                 let mut interval =
@@ -400,7 +716,13 @@ fn spawn_job_runner(ctx: Arc<Context>) {
 
                 loop {
                     interval.tick().await;
-                    db::run_scheduled_jobs(&ctx, &*pool.get().await)
+                    if jobs_shutting_down() {
+                        return;
+                    }
+                    if jobs_paused() {
+                        continue;
+                    }
+                    db::run_scheduled_jobs(&ctx, &*pool.get().await, &workers)
                         .await
                         .context("run database scheduled jobs")
                         .unwrap();
@@ -408,6 +730,7 @@ fn spawn_job_runner(ctx: Arc<Context>) {
             });
 
             match res.await {
+                Ok(()) => return,
                 Err(err) if err.is_panic() => {
                     /* handle panic in above task, re-launching */
                     tracing::error!("run_scheduled_jobs task died (error={err})");
@@ -419,6 +742,24 @@ fn spawn_job_runner(ctx: Arc<Context>) {
     });
 }
 
+/// The names the bot responds to when mentioned, from the comma-separated
+/// `TRIAGEBOT_TRIGGER_NAMES`, defaulting to its username and `triagebot` if
+/// that isn't set or lists no names.
+fn trigger_names(username: &str) -> Vec<String> {
+    let names: Vec<String> = env::var("TRIAGEBOT_TRIGGER_NAMES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().trim_start_matches('@'))
+        .filter(|name| !name.is_empty())
+        .map(str::to_owned)
+        .collect();
+    if names.is_empty() {
+        vec![username.to_owned(), "triagebot".to_owned()]
+    } else {
+        names
+    }
+}
+
 /// Determines whether or not background scheduled jobs should be disabled for
 /// the purpose of testing.
 ///
@@ -427,6 +768,16 @@ fn is_scheduled_jobs_disabled() -> bool {
     env::var_os("TRIAGEBOT_TEST_DISABLE_JOBS").is_some()
 }
 
+/// How many scheduled jobs may run at the same time, from
+/// `TRIAGEBOT_JOB_CONCURRENCY`.
+fn job_concurrency() -> usize {
+    env::var("TRIAGEBOT_JOB_CONCURRENCY")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(JOB_DEFAULT_CONCURRENCY)
+}
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
     dotenv::dotenv().ok();
@@ -188,7 +188,9 @@ fn handle_command<'a>(
                                 .await
                                 .map_err(|e| format_err!("Failed to await at this time: {e:?}"))
                             }
-                            Some("docs-update") => return trigger_docs_update(message_data),
+                            Some("docs-update") => {
+                                return trigger_docs_update(ctx.github.clone(), message_data)
+                            }
                             _ => {}
                         }
                     }
@@ -757,12 +759,12 @@ async fn post_waiter(
     Ok(None)
 }
 
-fn trigger_docs_update(message: &Message) -> anyhow::Result<Option<String>> {
+fn trigger_docs_update(gh: GithubClient, message: &Message) -> anyhow::Result<Option<String>> {
     let message = message.clone();
     // The default Zulip timeout of 10 seconds can be too short, so process in
     // the background.
     tokio::task::spawn(async move {
-        let response = match docs_update().await {
+        let response = match docs_update(&gh).await {
             Ok(None) => "No updates found.".to_string(),
             Ok(Some(pr)) => format!("Created docs update PR <{}>", pr.html_url),
             Err(e) => {